@@ -0,0 +1,436 @@
+//! Sound effects and music.
+//!
+//! Gameplay events and menu input are turned into [`SoundEffect`] events,
+//! which a backend then plays. The sounds are read from `assets/sounds`:
+//!
+//! | File          | Played when                            |
+//! |---------------|----------------------------------------|
+//! | `eat.ogg`     | a snake eats the fruit                 |
+//! | `turn.ogg`    | a snake changes its direction          |
+//! | `death.ogg`   | a snake dies                           |
+//! | `victory.ogg` | the snakes filled the whole board      |
+//! | `menu.ogg`    | a key is pressed on a menu screen      |
+//! | `music.ogg`   | all the time, looped                   |
+//!
+//! The shipped sounds were synthesized for the game and are in the public
+//! domain, see `assets/sounds/LICENSE.txt`. Missing files are skipped. The
+//! volumes are set on the controls screen. The music gets faster the longer
+//! the snakes grow and the higher the tick rate is. The null backend plays
+//! nothing and does not need a sound device, for CI and machines without
+//! speakers.
+
+use std::{collections::HashMap, fmt};
+
+use bevy::{asset::LoadState, audio::AudioSink, input::keyboard::KeyboardInput, prelude::*};
+
+use crate::{
+    config::{AudioConfig, GameConfig},
+    events::{FruitEaten, GameEnded, SnakeDied, SnakeTurned},
+    sim::GameState,
+    AppState,
+};
+
+/// The tick rate the music is played at its normal speed with
+const BASE_TICK_RATE: f64 = 5.0;
+/// How much faster the music gets when the snakes fill the whole board
+const FULL_BOARD_SPEEDUP: f32 = 0.5;
+/// Slowest and fastest the music is played
+const MUSIC_SPEED: (f32, f32) = (0.5, 2.0);
+/// How much a volume changes at once in the settings
+const VOLUME_STEP: f32 = 0.1;
+
+/// Plugin playing sounds for what happens in the game
+pub struct SoundPlugin {
+    pub backend: AudioBackend,
+}
+
+/// What plays the sounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioBackend {
+    /// The sound device, through the bevy `AudioPlugin`
+    Device,
+    /// Nothing, the sounds are dropped
+    Null,
+}
+
+impl SoundPlugin {
+    /// Plugin that plays nothing and needs no sound device
+    pub fn null() -> Self {
+        Self {
+            backend: AudioBackend::Null,
+        }
+    }
+}
+
+impl Default for SoundPlugin {
+    fn default() -> Self {
+        Self {
+            backend: AudioBackend::Device,
+        }
+    }
+}
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SoundEffect>()
+            .add_system(game_sound_system)
+            .add_system(menu_sound_system);
+
+        match self.backend {
+            AudioBackend::Device => {
+                app.add_startup_system(load_sounds_system)
+                    .add_system(
+                        play_sounds_system
+                            .after(game_sound_system)
+                            .after(menu_sound_system),
+                    )
+                    .init_resource::<Music>()
+                    .add_system(start_music_system)
+                    .add_system(music_tempo_system.after(start_music_system));
+            }
+            AudioBackend::Null => {
+                app.add_system(
+                    drop_sounds_system
+                        .after(game_sound_system)
+                        .after(menu_sound_system),
+                );
+            }
+        }
+    }
+}
+
+/// A short sound
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundEffect {
+    Eat,
+    Turn,
+    Death,
+    Victory,
+    /// Moving through a menu
+    Menu,
+}
+
+impl SoundEffect {
+    /// Every sound effect there is
+    pub const ALL: [SoundEffect; 5] = [
+        SoundEffect::Eat,
+        SoundEffect::Turn,
+        SoundEffect::Death,
+        SoundEffect::Victory,
+        SoundEffect::Menu,
+    ];
+
+    /// Path of the sound below the assets folder
+    pub fn path(self) -> &'static str {
+        match self {
+            SoundEffect::Eat => "sounds/eat.ogg",
+            SoundEffect::Turn => "sounds/turn.ogg",
+            SoundEffect::Death => "sounds/death.ogg",
+            SoundEffect::Victory => "sounds/victory.ogg",
+            SoundEffect::Menu => "sounds/menu.ogg",
+        }
+    }
+}
+
+/// One of the volumes of the [`AudioConfig`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Volume {
+    Master,
+    Sfx,
+    Music,
+}
+
+impl Volume {
+    /// Every volume, in the order they are listed in the settings
+    pub const ALL: [Volume; 3] = [Volume::Master, Volume::Sfx, Volume::Music];
+
+    /// The value of this volume in the config
+    pub fn get(self, audio: &AudioConfig) -> f32 {
+        match self {
+            Volume::Master => audio.master,
+            Volume::Sfx => audio.sfx,
+            Volume::Music => audio.music,
+        }
+    }
+
+    /// Turn this volume up or down by a number of steps, staying between 0
+    /// and 1
+    pub fn step(self, audio: &mut AudioConfig, steps: i32) {
+        let max = (1.0 / VOLUME_STEP).round() as i32;
+        let current = (self.get(audio) / VOLUME_STEP).round() as i32;
+        let volume = (current + steps).clamp(0, max) as f32 * VOLUME_STEP;
+        match self {
+            Volume::Master => audio.master = volume,
+            Volume::Sfx => audio.sfx = volume,
+            Volume::Music => audio.music = volume,
+        }
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Volume::Master => f.write_str("Volume"),
+            Volume::Sfx => f.write_str("Sound effects"),
+            Volume::Music => f.write_str("Music"),
+        }
+    }
+}
+
+/// How fast the music plays for a game, 1 being its normal speed
+pub fn music_speed(state: &GameState, config: &GameConfig) -> f32 {
+    let cells = (config.board_width * config.board_height) as f32;
+    let longest = state
+        .snakes()
+        .iter()
+        .map(|snake| snake.length())
+        .max()
+        .unwrap_or(1);
+    let filled = (longest - 1) as f32 / (cells - 1.0).max(1.0);
+
+    let tempo = (config.tick_rate / BASE_TICK_RATE).sqrt() as f32;
+    (tempo * (1.0 + FULL_BOARD_SPEEDUP * filled)).clamp(MUSIC_SPEED.0, MUSIC_SPEED.1)
+}
+
+/// Sound effects for what happened in the game
+pub fn game_sound_system(
+    mut eaten: EventReader<FruitEaten>,
+    mut turned: EventReader<SnakeTurned>,
+    mut died: EventReader<SnakeDied>,
+    mut ended: EventReader<GameEnded>,
+    mut sounds: EventWriter<SoundEffect>,
+) {
+    sounds.send_batch(eaten.iter().map(|_| SoundEffect::Eat));
+    sounds.send_batch(turned.iter().map(|_| SoundEffect::Turn));
+    sounds.send_batch(died.iter().map(|_| SoundEffect::Death));
+    sounds.send_batch(
+        ended
+            .iter()
+            .filter(|ended| ended.won)
+            .map(|_| SoundEffect::Victory),
+    );
+}
+
+/// A click for every key pressed on a menu screen. Reads the raw keyboard
+/// events, as the menus clear the keys they handle
+pub fn menu_sound_system(
+    mut keys: EventReader<KeyboardInput>,
+    app_state: Res<State<AppState>>,
+    mut sounds: EventWriter<SoundEffect>,
+) {
+    let pressed = keys.iter().filter(|key| key.state.is_pressed()).count();
+    let in_menu = !matches!(app_state.current(), AppState::Playing | AppState::Replay);
+    if in_menu && pressed > 0 {
+        sounds.send(SoundEffect::Menu);
+    }
+}
+
+/// The loaded sounds
+#[derive(Debug, Clone)]
+pub struct SoundLibrary {
+    effects: HashMap<SoundEffect, Handle<AudioSource>>,
+    music: Handle<AudioSource>,
+}
+
+/// Start loading every sound
+pub fn load_sounds_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let effects = SoundEffect::ALL
+        .into_iter()
+        .map(|sound| (sound, asset_server.load(sound.path())))
+        .collect();
+    commands.insert_resource(SoundLibrary {
+        effects,
+        music: asset_server.load("sounds/music.ogg"),
+    });
+}
+
+/// Play the sound effects on the sound device
+pub fn play_sounds_system(
+    mut sounds: EventReader<SoundEffect>,
+    library: Res<SoundLibrary>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    config: Res<GameConfig>,
+) {
+    for sound in sounds.iter() {
+        let handle = match library.effects.get(sound) {
+            Some(handle) => handle,
+            None => continue,
+        };
+        // Bevy keeps sounds that never load queued forever
+        if asset_server.get_load_state(handle) == LoadState::Loaded {
+            audio.play_with_settings(
+                handle.clone(),
+                PlaybackSettings::ONCE.with_volume(config.audio.sfx_volume()),
+            );
+        }
+    }
+}
+
+/// The playing music, once it is loaded
+#[derive(Debug, Clone, Default)]
+pub struct Music(Option<Handle<AudioSink>>);
+
+/// Start the music once it is loaded
+pub fn start_music_system(
+    mut music: ResMut<Music>,
+    library: Res<SoundLibrary>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    sinks: Res<Assets<AudioSink>>,
+    config: Res<GameConfig>,
+) {
+    if music.0.is_none() && asset_server.get_load_state(&library.music) == LoadState::Loaded {
+        let handle = audio.play_with_settings(
+            library.music.clone(),
+            PlaybackSettings::LOOP.with_volume(config.audio.music_volume()),
+        );
+        music.0 = Some(sinks.get_handle(handle));
+    }
+}
+
+/// Keep the speed and volume of the music up to date
+pub fn music_tempo_system(
+    music: Res<Music>,
+    sinks: Res<Assets<AudioSink>>,
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    app_state: Res<State<AppState>>,
+) {
+    let sink = match music.0.as_ref().and_then(|handle| sinks.get(handle)) {
+        Some(sink) => sink,
+        None => return,
+    };
+    let speed = match app_state.current() {
+        AppState::Playing | AppState::Replay => music_speed(&state, &config),
+        _ => 1.0,
+    };
+    sink.set_speed(speed);
+    sink.set_volume(config.audio.music_volume());
+}
+
+/// Drop the sound effects without playing them
+pub fn drop_sounds_system(mut sounds: EventReader<SoundEffect>) {
+    for sound in sounds.iter() {
+        debug!("Not playing {:?}", sound);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::event::Events, input::ButtonState};
+
+    use super::*;
+    use crate::{
+        events::GameEventsPlugin,
+        sim::{DeathCause, Direction},
+    };
+
+    /// An app with the null backend and nothing else that needs a device
+    fn null_app(state: AppState) -> App {
+        let mut app = App::new();
+        app.add_event::<KeyboardInput>()
+            .add_state(state)
+            .add_plugin(GameEventsPlugin)
+            .add_plugin(SoundPlugin::null());
+        app
+    }
+
+    fn send<E: Send + Sync + 'static>(app: &mut App, event: E) {
+        app.world.resource_mut::<Events<E>>().send(event);
+    }
+
+    fn key(state: ButtonState) -> KeyboardInput {
+        KeyboardInput {
+            scan_code: 0,
+            key_code: Some(KeyCode::Space),
+            state,
+        }
+    }
+
+    /// Update the app and return the sound effects sent during the update
+    fn sounds(app: &mut App) -> Vec<SoundEffect> {
+        app.update();
+        app.world
+            .resource::<Events<SoundEffect>>()
+            .iter_current_update_events()
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn game_events_make_sounds() {
+        let mut app = null_app(AppState::Playing);
+        send(
+            &mut app,
+            FruitEaten {
+                snake: 0,
+                fruit: Entity::from_raw(0),
+                pos: IVec2::ZERO,
+            },
+        );
+        for direction in [Direction::Up, Direction::Left] {
+            send(
+                &mut app,
+                SnakeTurned {
+                    snake: 0,
+                    direction,
+                },
+            );
+        }
+        send(
+            &mut app,
+            SnakeDied {
+                snake: 0,
+                cause: DeathCause::Wall,
+            },
+        );
+        send(&mut app, GameEnded { won: true });
+        assert_eq!(
+            sounds(&mut app),
+            [
+                SoundEffect::Eat,
+                SoundEffect::Turn,
+                SoundEffect::Turn,
+                SoundEffect::Death,
+                SoundEffect::Victory,
+            ]
+        );
+
+        // Losing has no sound of its own, the death already played
+        send(&mut app, GameEnded { won: false });
+        assert_eq!(sounds(&mut app), []);
+    }
+
+    #[test]
+    fn keys_click_only_in_menus() {
+        let mut app = null_app(AppState::MainMenu);
+        send(&mut app, key(ButtonState::Pressed));
+        send(&mut app, key(ButtonState::Pressed));
+        assert_eq!(sounds(&mut app), [SoundEffect::Menu]);
+        send(&mut app, key(ButtonState::Released));
+        assert_eq!(sounds(&mut app), []);
+
+        let mut app = null_app(AppState::Playing);
+        send(&mut app, key(ButtonState::Pressed));
+        assert_eq!(sounds(&mut app), []);
+    }
+
+    #[test]
+    fn volume_steps() {
+        let mut audio = AudioConfig {
+            master: 0.93,
+            sfx: 0.8,
+            music: 0.5,
+        };
+        Volume::Master.step(&mut audio, 1);
+        assert!((audio.master - 1.0).abs() < 1e-6);
+        Volume::Master.step(&mut audio, 1);
+        assert!((audio.master - 1.0).abs() < 1e-6);
+
+        Volume::Music.step(&mut audio, -3);
+        assert!((Volume::Music.get(&audio) - 0.2).abs() < 1e-6);
+        Volume::Sfx.step(&mut audio, -20);
+        assert_eq!(audio.sfx, 0.0);
+    }
+}
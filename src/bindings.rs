@@ -0,0 +1,728 @@
+//! Key bindings of every player and the screen to change them.
+//!
+//! Keys are bound to actions instead of being matched directly, so players
+//! with other keyboard layouts or the other hand on the keyboard can pick
+//! their own. The bindings are part of the [`GameConfig`] and saved back to
+//! the config file when changed in game. The same screen sets the volumes,
+//! which are saved the same way.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audio::Volume,
+    config::{ConfigError, GameConfig},
+    menu::{despawn_screen_system, spawn_screen, Screen, UiFont},
+    sim::Direction,
+    AppState,
+};
+
+/// Something a player can do with a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Turn counterclockwise, with relative controls
+    TurnLeft,
+    /// Turn clockwise, with relative controls or as the single switch
+    TurnRight,
+    /// Pause or resume the game
+    Pause,
+    /// Start the game over
+    Restart,
+}
+
+impl Action {
+    /// Every action, in the order they are listed on the bindings screen
+    pub const ALL: [Action; 8] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::TurnLeft,
+        Action::TurnRight,
+        Action::Pause,
+        Action::Restart,
+    ];
+
+    /// Keys the pause and game over screens use for something else, while
+    /// they check this action first. Binding them would hide the menu key
+    pub fn reserved_keys(self) -> &'static [KeyCode] {
+        match self {
+            // Q leaves the pause screen for the main menu
+            Action::Pause => &[KeyCode::Q],
+            // Escape leaves the game over screen for the main menu
+            Action::Restart => &[KeyCode::Q, KeyCode::Escape],
+            _ => &[],
+        }
+    }
+
+    /// The direction a snake turns to with this action
+    pub fn direction(self) -> Option<Direction> {
+        match self {
+            Action::Up => Some(Direction::Up),
+            Action::Down => Some(Direction::Down),
+            Action::Left => Some(Direction::Left),
+            Action::Right => Some(Direction::Right),
+            _ => None,
+        }
+    }
+}
+
+impl From<Direction> for Action {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Up => Action::Up,
+            Direction::Down => Action::Down,
+            Direction::Left => Action::Left,
+            Direction::Right => Action::Right,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::TurnLeft => f.write_str("Turn left"),
+            Action::TurnRight => f.write_str("Turn right"),
+            _ => fmt::Debug::fmt(self, f),
+        }
+    }
+}
+
+/// How the actions of a player turn the snake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ControlScheme {
+    /// Up, down, left and right point the snake in that direction
+    #[default]
+    Absolute,
+    /// Turn left and turn right turn the snake relative to its heading
+    Relative,
+    /// A single switch, turn right, turns the snake clockwise
+    OneSwitch,
+}
+
+impl ControlScheme {
+    /// Every scheme, in the order they are cycled through
+    pub const ALL: [ControlScheme; 3] = [
+        ControlScheme::Absolute,
+        ControlScheme::Relative,
+        ControlScheme::OneSwitch,
+    ];
+
+    /// The scheme after this one in [`ControlScheme::ALL`]
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&scheme| scheme == self);
+        Self::ALL[idx.map_or(0, |idx| (idx + 1) % Self::ALL.len())]
+    }
+
+    /// The direction a snake heading in `heading` turns to with the action,
+    /// `None` if the action does not turn with this scheme. A snake that
+    /// did not move yet counts as heading up
+    pub fn turn(self, action: Action, heading: Option<Direction>) -> Option<Direction> {
+        let heading = heading.unwrap_or(Direction::Up);
+        match (self, action) {
+            (ControlScheme::Absolute, _) => action.direction(),
+            (ControlScheme::Relative, Action::TurnLeft) => Some(heading.turned_left()),
+            (ControlScheme::Relative | ControlScheme::OneSwitch, Action::TurnRight) => {
+                Some(heading.turned_right())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ControlScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlScheme::Absolute => f.write_str("absolute"),
+            ControlScheme::Relative => f.write_str("relative"),
+            ControlScheme::OneSwitch => f.write_str("one switch"),
+        }
+    }
+}
+
+/// The keys bound to each action of a single player
+pub type ActionMap = BTreeMap<Action, Vec<KeyCode>>;
+
+/// An action of a specific player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    /// Index of the player
+    pub player: usize,
+    pub action: Action,
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "P{} {}", self.player + 1, self.action)
+    }
+}
+
+/// A key that can not be bound the way it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// The key is bound to more than one action
+    Twice {
+        key: KeyCode,
+        first: Binding,
+        second: Binding,
+    },
+    /// The key is one of the [`Action::reserved_keys`] of the action
+    Reserved { key: KeyCode, binding: Binding },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Twice { key, first, second } => {
+                write!(f, "{:?} is bound to both {} and {}", key, first, second)
+            }
+            Conflict::Reserved { key, binding } => {
+                write!(f, "{:?} is used by the menus, not for {}", key, binding)
+            }
+        }
+    }
+}
+
+/// The actions and control schemes of every player, by index.
+///
+/// Pause and restart work for everybody, no matter which player they are
+/// bound for. A single player can also use the keys of the second player.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub players: Vec<ActionMap>,
+    /// Players without an entry use [`ControlScheme::Absolute`]
+    pub schemes: Vec<ControlScheme>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use KeyCode::*;
+
+        let turns = |[up, down, left, right, turn_left, turn_right]: [KeyCode; 6]| {
+            ActionMap::from([
+                (Action::Up, vec![up]),
+                (Action::Down, vec![down]),
+                (Action::Left, vec![left]),
+                (Action::Right, vec![right]),
+                (Action::TurnLeft, vec![turn_left]),
+                (Action::TurnRight, vec![turn_right]),
+            ])
+        };
+        let mut first = turns([W, S, A, D, Q, E]);
+        first.insert(Action::Pause, vec![Escape, P]);
+        first.insert(Action::Restart, vec![R]);
+
+        Self {
+            players: vec![
+                first,
+                turns([Up, Down, Left, Right, Comma, Period]),
+                turns([I, K, J, L, U, O]),
+                turns([Numpad8, Numpad5, Numpad4, Numpad6, Numpad7, Numpad9]),
+            ],
+            schemes: Vec::new(),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Everything the key is bound to
+    fn bindings_of(&self, key: KeyCode) -> impl Iterator<Item = Binding> + '_ {
+        self.players
+            .iter()
+            .enumerate()
+            .flat_map(move |(player, map)| {
+                map.iter()
+                    .filter(move |(_, keys)| keys.contains(&key))
+                    .map(move |(&action, _)| Binding { player, action })
+            })
+    }
+
+    /// The action the key stands for in a game with the given number of
+    /// players
+    pub fn binding(&self, key: KeyCode, players: usize) -> Option<Binding> {
+        self.bindings_of(key)
+            .find_map(|binding| match binding.player {
+                player if player < players => Some(binding),
+                1 if players == 1 => Some(Binding {
+                    player: 0,
+                    ..binding
+                }),
+                _ => None,
+            })
+    }
+
+    /// The control scheme of a player
+    pub fn scheme(&self, player: usize) -> ControlScheme {
+        self.schemes.get(player).copied().unwrap_or_default()
+    }
+
+    /// Switch the player to the next control scheme
+    pub fn cycle_scheme(&mut self, player: usize) {
+        if self.schemes.len() <= player {
+            self.schemes.resize(player + 1, ControlScheme::default());
+        }
+        self.schemes[player] = self.schemes[player].next();
+    }
+
+    /// The keys bound to an action of a player
+    pub fn keys(&self, binding: Binding) -> &[KeyCode] {
+        self.players
+            .get(binding.player)
+            .and_then(|map| map.get(&binding.action))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Every key bound to the action for any player
+    pub fn all_keys(&self, action: Action) -> impl Iterator<Item = KeyCode> + '_ {
+        self.players
+            .iter()
+            .filter_map(move |map| map.get(&action))
+            .flatten()
+            .copied()
+    }
+
+    /// Check if any key of an action shared by all players was just pressed,
+    /// and keep it from triggering anything else in this frame
+    pub fn clear_just_pressed(&self, input: &mut Input<KeyCode>, action: Action) -> bool {
+        let keys = self.all_keys(action).collect::<Vec<_>>();
+        // Clear every key, not just the first one that was pressed
+        let mut pressed = false;
+        for key in keys {
+            pressed |= input.clear_just_pressed(key);
+        }
+        pressed
+    }
+
+    /// The keys of an action shared by all players, for help texts
+    pub fn describe(&self, action: Action) -> String {
+        let keys = self
+            .all_keys(action)
+            .map(|key| format!("{:?}", key))
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            String::from("unbound")
+        } else {
+            keys.join(" / ")
+        }
+    }
+
+    /// Bind the action to the key alone, unless the key is bound to another
+    /// action already or reserved for the menus
+    pub fn rebind(&mut self, binding: Binding, key: KeyCode) -> Result<(), Conflict> {
+        if binding.action.reserved_keys().contains(&key) {
+            return Err(Conflict::Reserved { key, binding });
+        }
+        if let Some(first) = self.bindings_of(key).find(|&other| other != binding) {
+            return Err(Conflict::Twice {
+                key,
+                first,
+                second: binding,
+            });
+        }
+
+        if self.players.len() <= binding.player {
+            self.players.resize_with(binding.player + 1, ActionMap::new);
+        }
+        self.players[binding.player].insert(binding.action, vec![key]);
+        Ok(())
+    }
+
+    /// Remove every key of the action
+    pub fn clear(&mut self, binding: Binding) {
+        if let Some(map) = self.players.get_mut(binding.player) {
+            map.remove(&binding.action);
+        }
+    }
+
+    /// Every key bound to more than one action or reserved for the menus
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut seen: Vec<(KeyCode, Binding)> = Vec::new();
+        let mut conflicts = Vec::new();
+        for (player, map) in self.players.iter().enumerate() {
+            for (&action, keys) in map {
+                let binding = Binding { player, action };
+                for &key in keys {
+                    if action.reserved_keys().contains(&key) {
+                        conflicts.push(Conflict::Reserved { key, binding });
+                    }
+                    match seen.iter().find(|(seen_key, _)| *seen_key == key) {
+                        Some(&(_, first)) if first != binding => conflicts.push(Conflict::Twice {
+                            key,
+                            first,
+                            second: binding,
+                        }),
+                        Some(_) => {}
+                        None => seen.push((key, binding)),
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+/// Where changed settings are saved, `None` if there is no config directory
+#[derive(Debug, Clone, Default)]
+pub struct ConfigPath(pub Option<PathBuf>);
+
+/// Plugin for the screen to change the key bindings
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConfigPath>()
+            .init_resource::<BindingsMenu>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Bindings).with_system(enter_bindings_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Bindings)
+                    .with_system(bindings_menu_system)
+                    .with_system(refresh_bindings_screen_system.after(bindings_menu_system)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Bindings).with_system(despawn_screen_system),
+            );
+    }
+}
+
+/// State of the bindings screen
+#[derive(Debug, Clone, Default)]
+pub struct BindingsMenu {
+    /// Player whose bindings are shown
+    player: usize,
+    /// Index of the selected line, the actions in [`Action::ALL`] followed by
+    /// the volumes in [`Volume::ALL`]
+    selected: usize,
+    /// Waiting for the key to bind the selected action to
+    waiting: bool,
+    /// Result of the last change
+    message: String,
+}
+
+/// Number of lines that can be selected
+const MENU_LINES: usize = Action::ALL.len() + Volume::ALL.len();
+
+impl BindingsMenu {
+    /// The selected action of the shown player, `None` if a volume is selected
+    fn binding(&self) -> Option<Binding> {
+        Action::ALL.get(self.selected).map(|&action| Binding {
+            player: self.player,
+            action,
+        })
+    }
+
+    /// The selected volume, `None` if an action is selected
+    fn volume(&self) -> Option<Volume> {
+        let idx = self.selected.checked_sub(Action::ALL.len())?;
+        Volume::ALL.get(idx).copied()
+    }
+}
+
+/// Start on the first action of the first player
+pub fn enter_bindings_system(mut menu: ResMut<BindingsMenu>) {
+    *menu = BindingsMenu::default();
+}
+
+/// Move through the actions and players and rebind the selected action, or
+/// change the selected volume
+pub fn bindings_menu_system(
+    mut input: ResMut<Input<KeyCode>>,
+    mut config: ResMut<GameConfig>,
+    config_path: Res<ConfigPath>,
+    mut menu: ResMut<BindingsMenu>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    let key = match input.get_just_pressed().next() {
+        Some(&key) => key,
+        None => return,
+    };
+    input.clear_just_pressed(key);
+
+    if menu.waiting {
+        menu.waiting = false;
+        menu.message = match menu.binding() {
+            Some(binding) if key != KeyCode::Escape => match config.bindings.rebind(binding, key) {
+                Ok(()) => save_bindings(&config, &config_path),
+                Err(Conflict::Twice { first, .. }) => {
+                    format!("{:?} is already used for {}", key, first)
+                }
+                Err(conflict) => conflict.to_string(),
+            },
+            _ => String::new(),
+        };
+    } else {
+        let players = config.bindings.players.len().max(1);
+        match (key, menu.volume()) {
+            (KeyCode::Up, _) => menu.selected = (menu.selected + MENU_LINES - 1) % MENU_LINES,
+            (KeyCode::Down, _) => menu.selected = (menu.selected + 1) % MENU_LINES,
+            (KeyCode::Left, None) => menu.player = (menu.player + players - 1) % players,
+            (KeyCode::Right, None) => menu.player = (menu.player + 1) % players,
+            (KeyCode::Left, Some(volume)) => {
+                volume.step(&mut config.audio, -1);
+                menu.message = save_audio(&config, &config_path);
+            }
+            (KeyCode::Right, Some(volume)) => {
+                volume.step(&mut config.audio, 1);
+                menu.message = save_audio(&config, &config_path);
+            }
+            (KeyCode::Return, None) => {
+                menu.waiting = true;
+                menu.message.clear();
+            }
+            (KeyCode::Tab, _) => {
+                let player = menu.player;
+                config.bindings.cycle_scheme(player);
+                menu.message = save_bindings(&config, &config_path);
+            }
+            (KeyCode::Back | KeyCode::Delete, None) => {
+                if let Some(binding) = menu.binding() {
+                    config.bindings.clear(binding);
+                }
+                menu.message = save_bindings(&config, &config_path);
+            }
+            (KeyCode::Escape, _) => {
+                let _ = app_state.replace(AppState::MainMenu);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Draw the screen again whenever something on it changed
+pub fn refresh_bindings_screen_system(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    config: Res<GameConfig>,
+    menu: Res<BindingsMenu>,
+    screens: Query<Entity, With<Screen>>,
+) {
+    if !menu.is_changed() && !config.is_changed() {
+        return;
+    }
+
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+    spawn_bindings_screen(&mut commands, &font, &config, &menu);
+}
+
+/// Save the bindings to the config file, returning a message for the user
+fn save_bindings(config: &GameConfig, path: &ConfigPath) -> String {
+    save_setting(path, "key bindings", |path| {
+        GameConfig::save_bindings(path, &config.bindings)
+    })
+}
+
+/// Save the volumes to the config file, returning a message for the user
+fn save_audio(config: &GameConfig, path: &ConfigPath) -> String {
+    save_setting(path, "volumes", |path| {
+        GameConfig::save_audio(path, &config.audio)
+    })
+}
+
+/// Save a setting with `save`, returning a message for the user
+fn save_setting(
+    path: &ConfigPath,
+    name: &str,
+    save: impl FnOnce(&Path) -> Result<(), ConfigError>,
+) -> String {
+    let path = match &path.0 {
+        Some(path) => path,
+        None => return String::from("No config directory, the change is not saved"),
+    };
+    match save(path) {
+        Ok(()) => String::from("Saved"),
+        Err(err) => {
+            warn!("Could not save the {}: {}", name, err);
+            String::from("Could not save the change")
+        }
+    }
+}
+
+fn spawn_bindings_screen(
+    commands: &mut Commands,
+    font: &UiFont,
+    config: &GameConfig,
+    menu: &BindingsMenu,
+) {
+    let bindings = &config.bindings;
+    let mut lines = vec![
+        format!(
+            "Player {}, {} controls",
+            menu.player + 1,
+            bindings.scheme(menu.player)
+        ),
+        String::new(),
+    ];
+    for (idx, &action) in Action::ALL.iter().enumerate() {
+        let binding = Binding {
+            player: menu.player,
+            action,
+        };
+        let keys = if idx == menu.selected && menu.waiting {
+            String::from("press a key, Escape to cancel")
+        } else {
+            let keys = bindings.keys(binding);
+            if keys.is_empty() {
+                String::from("-")
+            } else {
+                keys.iter()
+                    .map(|key| format!("{:?}", key))
+                    .collect::<Vec<_>>()
+                    .join(" / ")
+            }
+        };
+        let cursor = if idx == menu.selected { "> " } else { "" };
+        lines.push(format!("{}{}: {}", cursor, action, keys));
+    }
+    lines.push(String::new());
+    for volume in Volume::ALL {
+        let cursor = if menu.volume() == Some(volume) {
+            "> "
+        } else {
+            ""
+        };
+        let percent = volume.get(&config.audio) * 100.0;
+        lines.push(format!("{}{}: {:.0}%", cursor, volume, percent));
+    }
+    lines.push(String::new());
+    lines.push(menu.message.clone());
+    lines.push(String::from(
+        "Up / Down: select  Left / Right: player or volume  Tab: control scheme",
+    ));
+    lines.push(String::from(
+        "Enter: rebind  Backspace: clear  Escape: back",
+    ));
+
+    let lines = lines.iter().map(String::as_str).collect::<Vec<_>>();
+    spawn_screen(commands, font, "Controls and sound", &lines);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(player: usize, action: Action) -> Binding {
+        Binding { player, action }
+    }
+
+    #[test]
+    fn rebind_refuses_keys_of_other_players() {
+        let mut bindings = KeyBindings::default();
+        let err = bindings.rebind(binding(0, Action::Up), KeyCode::Up);
+        assert_eq!(
+            err,
+            Err(Conflict::Twice {
+                key: KeyCode::Up,
+                first: binding(1, Action::Up),
+                second: binding(0, Action::Up),
+            })
+        );
+        assert_eq!(bindings.keys(binding(0, Action::Up)), [KeyCode::W]);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn rebind_replaces_the_keys_of_the_action() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(bindings.rebind(binding(0, Action::Up), KeyCode::Z), Ok(()));
+        assert_eq!(bindings.keys(binding(0, Action::Up)), [KeyCode::Z]);
+        assert_eq!(bindings.binding(KeyCode::W, 1), None);
+
+        // Binding the key the action already has is no conflict
+        assert_eq!(bindings.rebind(binding(0, Action::Up), KeyCode::Z), Ok(()));
+    }
+
+    #[test]
+    fn conflicts_lists_keys_bound_twice() {
+        let bindings = KeyBindings {
+            players: vec![
+                ActionMap::from([
+                    (Action::Up, vec![KeyCode::W]),
+                    (Action::Down, vec![KeyCode::S, KeyCode::X]),
+                ]),
+                ActionMap::from([
+                    (Action::Up, vec![KeyCode::X]),
+                    (Action::Down, vec![KeyCode::K]),
+                ]),
+            ],
+            schemes: Vec::new(),
+        };
+        assert_eq!(
+            bindings.conflicts(),
+            [Conflict::Twice {
+                key: KeyCode::X,
+                first: binding(0, Action::Down),
+                second: binding(1, Action::Up),
+            }]
+        );
+        assert!(KeyBindings::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn menu_keys_are_reserved_for_pause_and_restart() {
+        let mut bindings = KeyBindings::default();
+        for (action, key) in [
+            (Action::Pause, KeyCode::Q),
+            (Action::Restart, KeyCode::Q),
+            (Action::Restart, KeyCode::Escape),
+        ] {
+            let binding = binding(0, action);
+            assert_eq!(
+                bindings.rebind(binding, key),
+                Err(Conflict::Reserved { key, binding })
+            );
+        }
+        assert_eq!(bindings, KeyBindings::default());
+        // Only the actions checked on the menus are kept off the menu keys
+        bindings.clear(binding(0, Action::TurnLeft));
+        assert_eq!(
+            bindings.rebind(binding(1, Action::TurnLeft), KeyCode::Q),
+            Ok(())
+        );
+
+        let bindings = KeyBindings {
+            players: vec![ActionMap::from([(Action::Restart, vec![KeyCode::Escape])])],
+            schemes: Vec::new(),
+        };
+        assert_eq!(
+            bindings.conflicts(),
+            [Conflict::Reserved {
+                key: KeyCode::Escape,
+                binding: binding(0, Action::Restart),
+            }]
+        );
+    }
+
+    #[test]
+    fn a_single_player_also_uses_the_keys_of_the_second() {
+        let bindings = KeyBindings::default();
+        assert_eq!(
+            bindings.binding(KeyCode::Left, 1),
+            Some(binding(0, Action::Left))
+        );
+        assert_eq!(
+            bindings.binding(KeyCode::Left, 2),
+            Some(binding(1, Action::Left))
+        );
+        assert_eq!(
+            bindings.binding(KeyCode::A, 1),
+            Some(binding(0, Action::Left))
+        );
+        // The keys of the third player do nothing with fewer players
+        assert_eq!(bindings.binding(KeyCode::J, 1), None);
+        assert_eq!(bindings.binding(KeyCode::J, 2), None);
+    }
+}
@@ -0,0 +1,240 @@
+//! Drawing the board the snakes move on.
+//!
+//! The board always matches the dimensions in the [`GameConfig`] and is laid
+//! out again whenever it changes. Walls surround every edge of the board that
+//! does not wrap around. Everything on the board is drawn in the layers given
+//! by the `_Z` constants, so the snakes are always on top of the fruit and
+//! both are on top of the board.
+
+use std::{fmt, str::FromStr};
+
+use bevy::{
+    ecs::system::EntityCommands,
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+
+/// Layer of the board itself
+pub const BOARD_Z: f32 = 0.0;
+/// Layer of the grid lines, above the board
+pub const PATTERN_Z: f32 = 0.1;
+/// Layer of the walls
+pub const WALL_Z: f32 = 0.2;
+/// Layer of the fruit
+pub const FRUIT_Z: f32 = 1.0;
+/// Layer of the snakes, above everything else
+pub const SNAKE_Z: f32 = 2.0;
+
+/// Thickness of the walls in cells
+const WALL_THICKNESS: f32 = 0.5;
+/// Thickness of the grid lines in cells
+const GRID_LINE_THICKNESS: f32 = 0.05;
+
+/// Plugin drawing the board and its walls
+pub struct BoardPlugin;
+
+impl Plugin for BoardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(layout_board_system);
+    }
+}
+
+/// How the cells of the board are told apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoardPattern {
+    /// A single color
+    #[default]
+    Plain,
+    /// Every other cell in the pattern color
+    Checkerboard,
+    /// Lines in the pattern color between the cells
+    Grid,
+}
+
+impl BoardPattern {
+    /// Every pattern there is
+    pub const ALL: &'static [BoardPattern] = &[
+        BoardPattern::Plain,
+        BoardPattern::Checkerboard,
+        BoardPattern::Grid,
+    ];
+
+    /// The name used in config files
+    pub fn name(self) -> &'static str {
+        match self {
+            BoardPattern::Plain => "plain",
+            BoardPattern::Checkerboard => "checkerboard",
+            BoardPattern::Grid => "grid",
+        }
+    }
+}
+
+impl fmt::Display for BoardPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BoardPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BoardPattern::ALL
+            .iter()
+            .copied()
+            .find(|pattern| pattern.name() == s)
+            .ok_or_else(|| {
+                let names = BoardPattern::ALL.iter().map(|pattern| pattern.name());
+                format!(
+                    "unknown board pattern `{}`, expected one of {}",
+                    s,
+                    names.collect::<Vec<_>>().join(", ")
+                )
+            })
+    }
+}
+
+/// Anything drawn as part of the board
+#[derive(Component, Debug)]
+pub struct BoardPart;
+
+/// Lay out the board again whenever the configuration changed
+pub fn layout_board_system(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut images: ResMut<Assets<Image>>,
+    parts: Query<Entity, With<BoardPart>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    for part in parts.iter() {
+        commands.entity(part).despawn();
+    }
+
+    let size = board_size(&config);
+    if config.board_pattern == BoardPattern::Checkerboard {
+        let texture = images.add(checkerboard(&config));
+        spawn_rect(&mut commands, Color::WHITE, Vec2::ZERO, size, BOARD_Z).insert(texture);
+    } else {
+        spawn_rect(
+            &mut commands,
+            config.colors.board,
+            Vec2::ZERO,
+            size,
+            BOARD_Z,
+        );
+    }
+
+    if config.board_pattern == BoardPattern::Grid {
+        let thickness = (config.cell_size * GRID_LINE_THICKNESS).max(1.0);
+        for x in 1..config.board_width {
+            let pos = Vec2::new(x as f32 * config.cell_size - size.x / 2.0, 0.0);
+            let line = Vec2::new(thickness, size.y);
+            spawn_rect(&mut commands, config.colors.pattern, pos, line, PATTERN_Z);
+        }
+        for y in 1..config.board_height {
+            let pos = Vec2::new(0.0, y as f32 * config.cell_size - size.y / 2.0);
+            let line = Vec2::new(size.x, thickness);
+            spawn_rect(&mut commands, config.colors.pattern, pos, line, PATTERN_Z);
+        }
+    }
+
+    // The walls overlap in the corners, so any of them closes a corner
+    let thickness = config.cell_size * WALL_THICKNESS;
+    let outer = field_size(&config);
+    let offset = (size + Vec2::splat(thickness)) / 2.0;
+    if !config.mode.wraps_horizontally() {
+        for x in [-offset.x, offset.x] {
+            let wall = Vec2::new(thickness, outer.y);
+            spawn_rect(
+                &mut commands,
+                config.colors.wall,
+                Vec2::new(x, 0.0),
+                wall,
+                WALL_Z,
+            );
+        }
+    }
+    if !config.mode.wraps_vertically() {
+        for y in [-offset.y, offset.y] {
+            let wall = Vec2::new(outer.x, thickness);
+            spawn_rect(
+                &mut commands,
+                config.colors.wall,
+                Vec2::new(0.0, y),
+                wall,
+                WALL_Z,
+            );
+        }
+    }
+}
+
+/// Size of the board in pixels, without the walls
+pub fn board_size(config: &GameConfig) -> Vec2 {
+    Vec2::new(config.board_width as f32, config.board_height as f32) * config.cell_size
+}
+
+/// Size of the board in pixels together with the walls around it. The walls
+/// count even where the board wraps around, so the layout is the same in
+/// every mode
+pub fn field_size(config: &GameConfig) -> Vec2 {
+    board_size(config) + Vec2::splat(config.cell_size * WALL_THICKNESS * 2.0)
+}
+
+/// Spawn a rectangle of the board centered on `pos`
+fn spawn_rect<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    color: Color,
+    pos: Vec2,
+    size: Vec2,
+    z: f32,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut ent = commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(size),
+            ..Default::default()
+        },
+        transform: Transform::from_translation(pos.extend(z)),
+        ..Default::default()
+    });
+    ent.insert(BoardPart);
+    ent
+}
+
+/// A texture with a pixel per cell, stretched over the board
+fn checkerboard(config: &GameConfig) -> Image {
+    let pixel = |color: Color| color.as_rgba_f32().map(|channel| (channel * 255.0) as u8);
+    let (board, pattern) = (pixel(config.colors.board), pixel(config.colors.pattern));
+
+    // Rows of the texture go from the top to the bottom of the board
+    let mut data = Vec::with_capacity((config.board_width * config.board_height * 4) as usize);
+    for y in (0..config.board_height).rev() {
+        for x in 0..config.board_width {
+            let color = if (x + y) % 2 == 0 { board } else { pattern };
+            data.extend_from_slice(&color);
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: config.board_width as u32,
+            height: config.board_height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    // Keep the cells sharp instead of blending them into each other
+    image.sampler_descriptor = ImageSampler::nearest();
+    image
+}
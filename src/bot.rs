@@ -0,0 +1,59 @@
+//! Simple computer player, used to run games without anybody at the keyboard
+
+use bevy::math::IVec2;
+
+use crate::sim::{Direction, GameState};
+
+/// Order in which directions are tried when they are equally good
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+/// Pick the direction that brings the snake closest to the fruit without
+/// running into a wall or a snake right away. Keeps the current direction
+/// if there is no way out.
+pub fn greedy_direction(state: &GameState, snake: usize) -> Option<Direction> {
+    let current = state.snakes().get(snake)?;
+    let head = current.head();
+    let fruit = match state.fruit() {
+        Some(fruit) => fruit,
+        None => return current.direction(),
+    };
+
+    DIRECTIONS
+        .iter()
+        .copied()
+        .filter(|&direction| Some(direction.opposite()) != current.direction())
+        .map(|direction| (direction, state.rules().neighbour(head, direction)))
+        .filter(|&(_, next)| is_free(state, next))
+        .min_by_key(|&(_, next)| distance(state, next, fruit))
+        .map(|(direction, _)| direction)
+        .or_else(|| current.direction())
+}
+
+/// Whether a snake could move into the cell without dying
+fn is_free(state: &GameState, cell: IVec2) -> bool {
+    state.board().contains(cell)
+        && state
+            .snakes()
+            .iter()
+            .all(|snake| snake.body().all(|part| part != cell))
+}
+
+/// Number of steps between two cells, taking the shortcut over wrapping
+/// edges
+fn distance(state: &GameState, a: IVec2, b: IVec2) -> i32 {
+    let board = state.board();
+    let mode = state.rules().mode;
+    let mut delta = (a - b).abs();
+    if mode.wraps_horizontally() {
+        delta.x = delta.x.min(board.width - delta.x);
+    }
+    if mode.wraps_vertically() {
+        delta.y = delta.y.min(board.height - delta.y);
+    }
+    delta.x + delta.y
+}
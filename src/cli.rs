@@ -0,0 +1,114 @@
+//! Command line arguments
+
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{
+    config::{ConfigError, GameConfig},
+    sim::GameMode,
+};
+
+/// Snake game implementation with bevy
+#[derive(Debug, Parser)]
+#[clap(name = "bevy_snake", version)]
+pub struct Cli {
+    /// Config file to use instead of config.ron in the user config directory
+    #[clap(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Override a config value, e.g. `--set tick_rate=8`. Can be repeated
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+    /// Width of the board in cells
+    #[clap(long)]
+    pub width: Option<i32>,
+    /// Height of the board in cells
+    #[clap(long)]
+    pub height: Option<i32>,
+    /// Simulation ticks per second
+    #[clap(long)]
+    pub tick_rate: Option<f64>,
+    /// Seed for all random choices
+    #[clap(long)]
+    pub seed: Option<u64>,
+    /// Game mode: classic, wrap, wrap_horizontal or wrap_vertical
+    #[clap(long, value_parser)]
+    pub mode: Option<GameMode>,
+    /// Number of local players, 1 to 4
+    #[clap(long)]
+    pub players: Option<usize>,
+    /// Initial window width in logical pixels
+    #[clap(long)]
+    pub window_width: Option<f32>,
+    /// Initial window height in logical pixels
+    #[clap(long)]
+    pub window_height: Option<f32>,
+    /// Start in fullscreen, F11 switches back to a window
+    #[clap(long)]
+    pub fullscreen: bool,
+    /// Play without sound, also on machines without a sound device
+    #[clap(long)]
+    pub mute: bool,
+    /// Play back a recorded game. Its own configuration is used for the rules
+    #[clap(long, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+    /// Let a bot play, or play the --replay, without opening a window and
+    /// print the results
+    #[clap(long)]
+    pub headless: bool,
+    /// Number of games to play with --headless
+    #[clap(long, default_value_t = 1, requires = "headless")]
+    pub games: u32,
+}
+
+impl Cli {
+    /// Build the configuration from the config file, the overrides and the
+    /// other arguments, in that order
+    pub fn game_config(&self) -> Result<GameConfig, ConfigError> {
+        let mut config = match (&self.config, self.config_path()) {
+            (Some(path), _) => GameConfig::load(path, true)?,
+            (None, Some(path)) => GameConfig::load(&path, false)?,
+            (None, None) => GameConfig::default(),
+        };
+
+        for assignment in &self.overrides {
+            config.apply_override(assignment)?;
+        }
+
+        if let Some(width) = self.width {
+            config.board_width = width;
+        }
+        if let Some(height) = self.height {
+            config.board_height = height;
+        }
+        if let Some(tick_rate) = self.tick_rate {
+            config.tick_rate = tick_rate;
+        }
+        if let Some(seed) = self.seed {
+            config.seed = Some(seed);
+        }
+        if let Some(mode) = self.mode {
+            config.mode = mode;
+        }
+        if let Some(players) = self.players {
+            config.players = players;
+        }
+        if let Some(window_width) = self.window_width {
+            config.window_width = window_width;
+        }
+        if let Some(window_height) = self.window_height {
+            config.window_height = window_height;
+        }
+        if self.fullscreen {
+            config.fullscreen = true;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The config file in use, `None` if there is no config directory
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(GameConfig::default_path)
+    }
+}
@@ -0,0 +1,622 @@
+//! Game configuration loaded from a RON file at startup
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bindings::KeyBindings,
+    board::BoardPattern,
+    sim::{Board, GameMode, Rules},
+};
+
+/// Most local players a game can have
+pub const MAX_PLAYERS: usize = 4;
+
+/// Everything that can be tuned without recompiling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    /// Width of the board in cells
+    pub board_width: i32,
+    /// Height of the board in cells
+    pub board_height: i32,
+    /// Size of a cell in pixels
+    pub cell_size: f32,
+    /// Simulation ticks per second
+    pub tick_rate: f64,
+    /// Seed for all random choices, a random one if not set
+    pub seed: Option<u64>,
+    pub mode: GameMode,
+    /// Number of local players
+    pub players: usize,
+    /// Turns a player can enter ahead of the snake
+    pub input_buffer: usize,
+    /// How far a gamepad stick has to be pushed to turn, from 0 to 1
+    pub gamepad_deadzone: f32,
+    /// Initial window width in logical pixels
+    pub window_width: f32,
+    /// Initial window height in logical pixels
+    pub window_height: f32,
+    /// Start in fullscreen instead of a window
+    pub fullscreen: bool,
+    /// How the cells of the board are told apart
+    pub board_pattern: BoardPattern,
+    pub colors: ColorConfig,
+    pub bindings: KeyBindings,
+    pub audio: AudioConfig,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            board_width: 21,
+            board_height: 21,
+            cell_size: 50.0,
+            tick_rate: 5.0,
+            seed: None,
+            mode: GameMode::Classic,
+            players: 1,
+            input_buffer: 3,
+            gamepad_deadzone: 0.5,
+            window_width: 1280.0,
+            window_height: 720.0,
+            fullscreen: false,
+            board_pattern: BoardPattern::Plain,
+            colors: ColorConfig::default(),
+            bindings: KeyBindings::default(),
+            audio: AudioConfig::default(),
+        }
+    }
+}
+
+/// Colors of the things on screen, written as hex strings like `"ff0000"`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorConfig {
+    /// Around the board
+    #[serde(with = "hex_color")]
+    pub background: Color,
+    #[serde(with = "hex_color")]
+    pub board: Color,
+    /// The other cells of a checkerboard or the grid lines
+    #[serde(with = "hex_color")]
+    pub pattern: Color,
+    /// Around the edges of the board that do not wrap around
+    #[serde(with = "hex_color")]
+    pub wall: Color,
+    /// One color per player, repeated if there are more players than colors
+    #[serde(with = "hex_colors")]
+    pub snakes: Vec<Color>,
+    #[serde(with = "hex_color")]
+    pub fruit: Color,
+}
+
+impl ColorConfig {
+    /// The color of the snake with the given index
+    pub fn snake(&self, snake: usize) -> Color {
+        match self.snakes.len() {
+            0 => Color::RED,
+            len => self.snakes[snake % len],
+        }
+    }
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self {
+            background: Color::GRAY,
+            board: Color::BLACK,
+            pattern: Color::rgb(0.1, 0.1, 0.1),
+            wall: Color::DARK_GRAY,
+            snakes: vec![Color::RED, Color::BLUE, Color::YELLOW, Color::FUCHSIA],
+            fruit: Color::GREEN,
+        }
+    }
+}
+
+/// Volumes from 0 for silence to 1 for full volume
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    /// Applies to everything
+    pub master: f32,
+    /// Sound effects
+    pub sfx: f32,
+    pub music: f32,
+}
+
+impl AudioConfig {
+    /// Volume the sound effects are played at
+    pub fn sfx_volume(&self) -> f32 {
+        self.master * self.sfx
+    }
+
+    /// Volume the music is played at
+    pub fn music_volume(&self) -> f32 {
+        self.master * self.music
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            sfx: 0.8,
+            music: 0.5,
+        }
+    }
+}
+
+/// Reasons a configuration could not be used
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, ron::Error),
+    /// The configuration could not be written
+    Save(PathBuf, ron::Error),
+    /// A `key=value` override that could not be applied
+    Override(String),
+    /// A value outside of its allowed range
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => {
+                write!(f, "invalid config {}: {}", path.display(), err)
+            }
+            ConfigError::Save(path, err) => {
+                write!(f, "could not save config {}: {}", path.display(), err)
+            }
+            ConfigError::Override(msg) => write!(f, "invalid override: {}", msg),
+            ConfigError::Invalid(msg) => write!(f, "invalid config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl GameConfig {
+    /// `config.ron` in the config directory of the user
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("bevy_snake").join("config.ron"))
+    }
+
+    /// Read the configuration from a file. If `required` is false a missing
+    /// file gives the default configuration
+    pub fn load(path: &Path, required: bool) -> Result<Self, ConfigError> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if !required && err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(err) => return Err(ConfigError::Io(path.to_owned(), err)),
+        };
+        ron::from_str(&content).map_err(|err| ConfigError::Parse(path.to_owned(), err))
+    }
+
+    /// Write the configuration to a file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let content = ron::ser::to_string_pretty(self, PrettyConfig::new().compact_arrays(true))
+            .map_err(|err| ConfigError::Save(path.to_owned(), err))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+        }
+        fs::write(path, content).map_err(|err| ConfigError::Io(path.to_owned(), err))
+    }
+
+    /// Replace only the key bindings in the config file. Everything else in
+    /// it stays as it is, even if it was overridden for this run
+    pub fn save_bindings(path: &Path, bindings: &KeyBindings) -> Result<(), ConfigError> {
+        let mut config = Self::load(path, false)?;
+        config.bindings = bindings.clone();
+        config.save(path)
+    }
+
+    /// Replace only the volumes in the config file, like
+    /// [`GameConfig::save_bindings`]
+    pub fn save_audio(path: &Path, audio: &AudioConfig) -> Result<(), ConfigError> {
+        let mut config = Self::load(path, false)?;
+        config.audio = *audio;
+        config.save(path)
+    }
+
+    /// Apply an override in the form `key=value`, e.g. `colors.fruit=00ff00`.
+    /// The color of a single player is set with `colors.snakes.<player>`,
+    /// counting players from 1
+    pub fn apply_override(&mut self, assignment: &str) -> Result<(), ConfigError> {
+        let (key, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| ConfigError::Override(format!("`{}` is not key=value", assignment)))?;
+        let (key, value) = (key.trim(), value.trim());
+
+        fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+            value
+                .parse()
+                .map_err(|_| ConfigError::Override(format!("`{}` is no valid {}", value, key)))
+        }
+        fn color(key: &str, value: &str) -> Result<Color, ConfigError> {
+            hex_color::parse(value)
+                .map_err(|err| ConfigError::Override(format!("{}: {}", key, err)))
+        }
+
+        match key {
+            "board_width" => self.board_width = parse(key, value)?,
+            "board_height" => self.board_height = parse(key, value)?,
+            "cell_size" => self.cell_size = parse(key, value)?,
+            "tick_rate" => self.tick_rate = parse(key, value)?,
+            "seed" => self.seed = Some(parse(key, value)?),
+            "mode" => {
+                self.mode = value
+                    .parse()
+                    .map_err(|err| ConfigError::Override(format!("{}: {}", key, err)))?
+            }
+            "players" => self.players = parse(key, value)?,
+            "input_buffer" => self.input_buffer = parse(key, value)?,
+            "gamepad_deadzone" => self.gamepad_deadzone = parse(key, value)?,
+            "window_width" => self.window_width = parse(key, value)?,
+            "window_height" => self.window_height = parse(key, value)?,
+            "fullscreen" => self.fullscreen = parse(key, value)?,
+            "board_pattern" => {
+                self.board_pattern = value
+                    .parse()
+                    .map_err(|err| ConfigError::Override(format!("{}: {}", key, err)))?
+            }
+            "colors.background" => self.colors.background = color(key, value)?,
+            "colors.board" => self.colors.board = color(key, value)?,
+            "colors.pattern" => self.colors.pattern = color(key, value)?,
+            "colors.wall" => self.colors.wall = color(key, value)?,
+            "colors.fruit" => self.colors.fruit = color(key, value)?,
+            "audio.master" => self.audio.master = parse(key, value)?,
+            "audio.sfx" => self.audio.sfx = parse(key, value)?,
+            "audio.music" => self.audio.music = parse(key, value)?,
+            _ => match key.strip_prefix("colors.snakes.") {
+                Some(player) => {
+                    let player: usize = parse(key, player)?;
+                    if !(1..=MAX_PLAYERS).contains(&player) {
+                        return Err(ConfigError::Override(format!(
+                            "{}: players go from 1 to {}",
+                            key, MAX_PLAYERS
+                        )));
+                    }
+                    let default = ColorConfig::default();
+                    while self.colors.snakes.len() < player {
+                        let idx = self.colors.snakes.len();
+                        self.colors.snakes.push(default.snake(idx));
+                    }
+                    self.colors.snakes[player - 1] = color(key, value)?;
+                }
+                None => return Err(ConfigError::Override(format!("unknown key `{}`", key))),
+            },
+        }
+        Ok(())
+    }
+
+    /// Check that all values are in a range the game can work with
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        const BOARD_SIZE: std::ops::RangeInclusive<i32> = 2..=1000;
+        const PLAYERS: std::ops::RangeInclusive<usize> = 1..=MAX_PLAYERS;
+        const INPUT_BUFFER: std::ops::RangeInclusive<usize> = 1..=16;
+
+        if !BOARD_SIZE.contains(&self.board_width) {
+            return Err(ConfigError::Invalid(format!(
+                "board_width is {}, it has to be between {} and {}",
+                self.board_width,
+                BOARD_SIZE.start(),
+                BOARD_SIZE.end()
+            )));
+        }
+        if !BOARD_SIZE.contains(&self.board_height) {
+            return Err(ConfigError::Invalid(format!(
+                "board_height is {}, it has to be between {} and {}",
+                self.board_height,
+                BOARD_SIZE.start(),
+                BOARD_SIZE.end()
+            )));
+        }
+        if !(self.cell_size.is_finite() && self.cell_size > 0.0) {
+            return Err(ConfigError::Invalid(format!(
+                "cell_size is {}, it has to be a positive number",
+                self.cell_size
+            )));
+        }
+        if !(self.tick_rate.is_finite() && self.tick_rate > 0.0 && self.tick_rate <= 1000.0) {
+            return Err(ConfigError::Invalid(format!(
+                "tick_rate is {}, it has to be above 0 and at most 1000",
+                self.tick_rate
+            )));
+        }
+        if !PLAYERS.contains(&self.players) {
+            return Err(ConfigError::Invalid(format!(
+                "players is {}, it has to be between {} and {}",
+                self.players,
+                PLAYERS.start(),
+                PLAYERS.end()
+            )));
+        }
+        if !INPUT_BUFFER.contains(&self.input_buffer) {
+            return Err(ConfigError::Invalid(format!(
+                "input_buffer is {}, it has to be between {} and {}",
+                self.input_buffer,
+                INPUT_BUFFER.start(),
+                INPUT_BUFFER.end()
+            )));
+        }
+        if !(self.gamepad_deadzone > 0.0 && self.gamepad_deadzone < 1.0) {
+            return Err(ConfigError::Invalid(format!(
+                "gamepad_deadzone is {}, it has to be above 0 and below 1",
+                self.gamepad_deadzone
+            )));
+        }
+        if self.bindings.players.len() < self.players {
+            return Err(ConfigError::Invalid(format!(
+                "bindings has keys for {} players, {} are playing",
+                self.bindings.players.len(),
+                self.players
+            )));
+        }
+        if let Some(conflict) = self.bindings.conflicts().first() {
+            return Err(ConfigError::Invalid(format!("bindings: {}", conflict)));
+        }
+        if self.colors.snakes.is_empty() {
+            return Err(ConfigError::Invalid(String::from(
+                "colors.snakes needs at least one color",
+            )));
+        }
+        if self.board_width < self.players as i32 * 2 {
+            return Err(ConfigError::Invalid(format!(
+                "board_width is {}, {} players need at least {}",
+                self.board_width,
+                self.players,
+                self.players * 2
+            )));
+        }
+        for (name, volume) in [
+            ("audio.master", self.audio.master),
+            ("audio.sfx", self.audio.sfx),
+            ("audio.music", self.audio.music),
+        ] {
+            if !(0.0..=1.0).contains(&volume) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} is {}, it has to be between 0 and 1",
+                    name, volume
+                )));
+            }
+        }
+        for (name, size) in [
+            ("window_width", self.window_width),
+            ("window_height", self.window_height),
+        ] {
+            if !(size.is_finite() && size > 0.0) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} is {}, it has to be a positive number",
+                    name, size
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The rules every game is played with
+    pub fn rules(&self) -> Rules {
+        Rules {
+            board: Board::new(self.board_width, self.board_height),
+            mode: self.mode,
+            players: self.players,
+        }
+    }
+
+    /// Seconds between two ticks of the simulation
+    pub fn tick_step(&self) -> f64 {
+        1.0 / self.tick_rate
+    }
+}
+
+/// (De)serialize colors as hex strings
+mod hex_color {
+    use bevy::prelude::Color;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn parse(value: &str) -> Result<Color, String> {
+        Color::hex(value.trim_start_matches('#'))
+            .map_err(|_| format!("`{}` is no hex color like \"ff0000\"", value))
+    }
+
+    pub fn to_hex(color: &Color) -> String {
+        let [r, g, b, a] = color.as_rgba_f32().map(|c| (c * 255.0).round() as u8);
+        if a == u8::MAX {
+            format!("{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            format!("{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_hex(color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        parse(&value).map_err(D::Error::custom)
+    }
+}
+
+/// (De)serialize lists of colors as lists of hex strings
+mod hex_colors {
+    use bevy::prelude::Color;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::hex_color;
+
+    pub fn serialize<S: Serializer>(colors: &[Color], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(colors.iter().map(hex_color::to_hex))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Color>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|value| hex_color::parse(value).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load a config file with the given content
+    fn load_str(name: &str, content: &str) -> Result<GameConfig, ConfigError> {
+        let path =
+            std::env::temp_dir().join(format!("bevy_snake_{}_{}.ron", name, std::process::id()));
+        fs::write(&path, content).unwrap();
+        let config = GameConfig::load(&path, true);
+        fs::remove_file(path).unwrap();
+        config
+    }
+
+    /// The message of a validation error
+    fn invalid(config: GameConfig) -> String {
+        match config.validate() {
+            Err(ConfigError::Invalid(msg)) => msg,
+            other => panic!("expected an invalid config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let err = load_str("unknown_key", "(board_widht: 30)").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(..)));
+        let err = load_str("unknown_color", "(colors: (snake: \"ff0000\"))").unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(..)));
+
+        let err = GameConfig::default()
+            .apply_override("board_widht=30")
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown key `board_widht`"));
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        let err = load_str("bad_color", "(colors: (fruit: \"green\"))").unwrap_err();
+        assert!(err.to_string().contains("`green` is no hex color"));
+        let err = load_str(
+            "bad_snake_color",
+            "(colors: (snakes: [\"ff0000\", \"#12\"]))",
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(..)));
+
+        let mut config = GameConfig::default();
+        for assignment in [
+            "colors.fruit=green",
+            "board_width=wide",
+            "mode=spiral",
+            "fullscreen=1",
+            "no_assignment",
+        ] {
+            let err = config.apply_override(assignment).unwrap_err();
+            assert!(matches!(err, ConfigError::Override(_)), "{}", assignment);
+        }
+        assert_eq!(config, GameConfig::default());
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        assert!(GameConfig::default().validate().is_ok());
+
+        let msg = invalid(GameConfig {
+            board_width: 5,
+            players: 3,
+            ..Default::default()
+        });
+        assert_eq!(msg, "board_width is 5, 3 players need at least 6");
+        let msg = invalid(GameConfig {
+            players: 0,
+            ..Default::default()
+        });
+        assert!(msg.starts_with("players is 0"));
+
+        for key in ["audio.master", "audio.sfx", "audio.music"] {
+            let mut config = GameConfig::default();
+            config.apply_override(&format!("{}=1.5", key)).unwrap();
+            assert_eq!(
+                invalid(config),
+                format!("{} is 1.5, it has to be between 0 and 1", key)
+            );
+        }
+
+        let msg = invalid(GameConfig {
+            window_width: 0.0,
+            ..Default::default()
+        });
+        assert_eq!(msg, "window_width is 0, it has to be a positive number");
+        let msg = invalid(GameConfig {
+            window_height: f32::NAN,
+            ..Default::default()
+        });
+        assert!(msg.starts_with("window_height is NaN"));
+    }
+
+    #[test]
+    fn snake_colors_are_set_by_player() {
+        let mut config = GameConfig::default();
+        config.colors.snakes.truncate(1);
+
+        // Players without a color of their own get their default one
+        config.apply_override("colors.snakes.3=00ff00").unwrap();
+        let default = ColorConfig::default();
+        assert_eq!(
+            config.colors.snakes,
+            [
+                default.snakes[0],
+                default.snakes[1],
+                Color::rgb(0.0, 1.0, 0.0)
+            ]
+        );
+        config.apply_override("colors.snakes.1=0000ff").unwrap();
+        assert_eq!(config.colors.snake(0), Color::rgb(0.0, 0.0, 1.0));
+        assert_eq!(config.colors.snakes.len(), 3);
+
+        for assignment in [
+            "colors.snakes.0=ff0000",
+            "colors.snakes.5=ff0000",
+            "colors.snakes.first=ff0000",
+        ] {
+            let err = config.apply_override(assignment).unwrap_err();
+            assert!(matches!(err, ConfigError::Override(_)), "{}", assignment);
+        }
+        assert_eq!(config.colors.snakes.len(), 3);
+    }
+
+    #[test]
+    fn default_config_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("bevy_snake_round_trip_{}.ron", std::process::id()));
+        let default = GameConfig::default();
+        default.save(&path).unwrap();
+        let loaded = GameConfig::load(&path, true);
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+
+        // Colors are saved with 8 bits per channel, so compare them as saved
+        let colors = ron::to_string(&loaded.colors).unwrap();
+        assert_eq!(colors, ron::to_string(&default.colors).unwrap());
+        assert_eq!(
+            GameConfig {
+                colors: default.colors.clone(),
+                ..loaded
+            },
+            default
+        );
+        assert!(saved.contains("board_width: 21"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Turning player input into the turns the snakes take
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{
+    bindings::Binding,
+    config::GameConfig,
+    sim::{Direction, GameState},
+};
+
+/// Turns a player entered that were not taken yet, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct TurnQueue {
+    turns: VecDeque<Direction>,
+}
+
+impl TurnQueue {
+    /// Queue a turn for a snake currently moving in `current`.
+    ///
+    /// Turns that repeat the direction the snake will be moving in or point
+    /// back into its neck are dropped, as are turns past `depth`. Returns
+    /// whether the turn was queued.
+    pub fn push(&mut self, direction: Direction, current: Option<Direction>, depth: usize) -> bool {
+        let last = self.planned(current);
+        if last == Some(direction) || last == Some(direction.opposite()) {
+            return false;
+        }
+        if self.turns.len() >= depth {
+            return false;
+        }
+
+        self.turns.push_back(direction);
+        true
+    }
+
+    /// The direction the snake will move in once all queued turns are taken
+    pub fn planned(&self, current: Option<Direction>) -> Option<Direction> {
+        self.turns.back().copied().or(current)
+    }
+
+    /// Take the turn for the next tick
+    pub fn pop(&mut self) -> Option<Direction> {
+        self.turns.pop_front()
+    }
+
+    pub fn clear(&mut self) {
+        self.turns.clear();
+    }
+}
+
+/// The [`TurnQueue`] of every snake, by index
+#[derive(Debug, Clone, Default)]
+pub struct TurnQueues(pub Vec<TurnQueue>);
+
+impl TurnQueues {
+    /// Empty queues for the given number of snakes
+    pub fn new(snakes: usize) -> Self {
+        Self(vec![TurnQueue::default(); snakes])
+    }
+
+    /// Take the turn of every snake for the next tick
+    pub fn pop_all(&mut self) -> Vec<Option<Direction>> {
+        self.0.iter_mut().map(TurnQueue::pop).collect()
+    }
+
+    /// Queue the turn an action of a player stands for with the control
+    /// scheme of that player. Returns whether a turn was queued
+    pub fn push_action(
+        &mut self,
+        state: &GameState,
+        config: &GameConfig,
+        binding: Binding,
+    ) -> bool {
+        let player = binding.player;
+        let (queue, snake) = match (self.0.get_mut(player), state.snakes().get(player)) {
+            (Some(queue), Some(snake)) => (queue, snake),
+            _ => return false,
+        };
+
+        let heading = queue.planned(snake.direction());
+        match config.bindings.scheme(player).turn(binding.action, heading) {
+            Some(direction) => queue.push(direction, snake.direction(), config.input_buffer),
+            None => false,
+        }
+    }
+}
+
+/// Get the keyborad input and queue it for the snake of each player
+pub fn snake_input_system(
+    mut queues: ResMut<TurnQueues>,
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    input: Res<Input<KeyCode>>,
+) {
+    for &key in input.get_just_pressed() {
+        if let Some(binding) = config.bindings.binding(key, state.snakes().len()) {
+            queues.push_action(&state, &config, binding);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Direction::*;
+
+    #[test]
+    fn quick_turns_are_all_kept() {
+        let mut queue = TurnQueue::default();
+        assert!(queue.push(Up, Some(Right), 3));
+        assert!(queue.push(Left, Some(Right), 3));
+        assert_eq!(queue.planned(Some(Right)), Some(Left));
+
+        assert_eq!(queue.pop(), Some(Up));
+        assert_eq!(queue.pop(), Some(Left));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn reversals_are_checked_against_the_planned_direction() {
+        let mut queue = TurnQueue::default();
+        assert!(!queue.push(Left, Some(Right), 3));
+
+        // Left is a valid turn once the snake is going up, down is not
+        assert!(queue.push(Up, Some(Right), 3));
+        assert!(!queue.push(Down, Some(Right), 3));
+        assert!(queue.push(Left, Some(Right), 3));
+        assert_eq!(queue.pop(), Some(Up));
+        assert_eq!(queue.pop(), Some(Left));
+    }
+
+    #[test]
+    fn repeated_directions_are_dropped() {
+        let mut queue = TurnQueue::default();
+        assert!(!queue.push(Right, Some(Right), 3));
+        assert!(queue.push(Up, Some(Right), 3));
+        assert!(!queue.push(Up, Some(Right), 3));
+        assert_eq!(queue.pop(), Some(Up));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn a_snake_without_direction_takes_any_turn() {
+        let mut queue = TurnQueue::default();
+        assert!(queue.push(Down, None, 3));
+        assert_eq!(queue.planned(None), Some(Down));
+    }
+
+    #[test]
+    fn depth_limits_the_queued_turns() {
+        let mut queue = TurnQueue::default();
+        assert!(queue.push(Up, Some(Right), 2));
+        assert!(queue.push(Left, Some(Right), 2));
+        assert!(!queue.push(Down, Some(Right), 2));
+
+        assert_eq!(queue.pop(), Some(Up));
+        assert!(queue.push(Down, Some(Up), 2));
+        assert_eq!(queue.pop(), Some(Left));
+        assert_eq!(queue.pop(), Some(Down));
+    }
+}
@@ -0,0 +1,189 @@
+//! What happens in a game, as bevy events.
+//!
+//! The events are sent right after every tick of the simulation, both while
+//! playing and while watching a replay, in the order they happened in. Audio,
+//! scoring, achievements or telemetry can read them without touching the
+//! rules.
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    game::Fruit,
+    sim::{DeathCause, Direction, GameEvent},
+};
+
+/// A snake ate the fruit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FruitEaten {
+    /// Index of the snake in the [`GameState`](crate::sim::GameState)
+    pub snake: usize,
+    /// The fruit entity, it is moved to the next free cell right away
+    pub fruit: Entity,
+    pub pos: IVec2,
+}
+
+/// A snake got longer, the tick after it ate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnakeGrew {
+    pub snake: usize,
+    /// The length after growing
+    pub length: usize,
+}
+
+/// A snake ran into something
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnakeDied {
+    pub snake: usize,
+    pub cause: DeathCause,
+}
+
+/// A snake changed its direction, or got its first one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnakeTurned {
+    pub snake: usize,
+    pub direction: Direction,
+}
+
+/// The game is over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameEnded {
+    /// Whether the snakes filled the whole board
+    pub won: bool,
+}
+
+/// Plugin adding the events of a game
+pub struct GameEventsPlugin;
+
+impl Plugin for GameEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FruitEaten>()
+            .add_event::<SnakeGrew>()
+            .add_event::<SnakeDied>()
+            .add_event::<SnakeTurned>()
+            .add_event::<GameEnded>();
+    }
+}
+
+/// Writers for all events of a game
+#[derive(SystemParam)]
+pub struct GameEvents<'w, 's> {
+    fruit_eaten: EventWriter<'w, 's, FruitEaten>,
+    snake_grew: EventWriter<'w, 's, SnakeGrew>,
+    snake_died: EventWriter<'w, 's, SnakeDied>,
+    snake_turned: EventWriter<'w, 's, SnakeTurned>,
+    game_ended: EventWriter<'w, 's, GameEnded>,
+    fruits: Query<'w, 's, Entity, With<Fruit>>,
+}
+
+impl<'w, 's> GameEvents<'w, 's> {
+    /// Send the events of a simulation step
+    pub fn send(&mut self, events: &[GameEvent]) {
+        let won = events.contains(&GameEvent::Won);
+        for event in events {
+            match *event {
+                GameEvent::SnakeTurned { snake, direction } => {
+                    self.snake_turned.send(SnakeTurned { snake, direction })
+                }
+                GameEvent::SnakeDied { snake, cause } => {
+                    self.snake_died.send(SnakeDied { snake, cause })
+                }
+                GameEvent::SnakeGrew { snake, length } => {
+                    self.snake_grew.send(SnakeGrew { snake, length })
+                }
+                GameEvent::FruitEaten { snake, pos } => {
+                    if let Ok(fruit) = self.fruits.get_single() {
+                        self.fruit_eaten.send(FruitEaten { snake, fruit, pos });
+                    }
+                }
+                GameEvent::GameOver => self.game_ended.send(GameEnded { won }),
+                GameEvent::FruitSpawned { .. } | GameEvent::Won => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+
+    use super::*;
+
+    /// The simulation events sent on the next update
+    struct Step(Vec<GameEvent>);
+
+    fn send_step_system(step: Res<Step>, mut events: GameEvents) {
+        events.send(&step.0);
+    }
+
+    /// The events of type `E` sent during the last update
+    fn sent<E: Copy + Send + Sync + 'static>(app: &App) -> Vec<E> {
+        app.world
+            .resource::<Events<E>>()
+            .iter_current_update_events()
+            .copied()
+            .collect()
+    }
+
+    /// Send `events` through [`GameEvents`] in an app with a single fruit
+    fn app(events: Vec<GameEvent>) -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugin(GameEventsPlugin)
+            .insert_resource(Step(events))
+            .add_system(send_step_system);
+        let fruit = app.world.spawn().insert(Fruit).id();
+        app.update();
+        (app, fruit)
+    }
+
+    #[test]
+    fn every_game_event_arrives_as_its_bevy_event() {
+        let pos = IVec2::new(3, 4);
+        let cause = DeathCause::OtherSnake { snake: 0 };
+        let (app, fruit) = app(vec![
+            GameEvent::SnakeTurned {
+                snake: 1,
+                direction: Direction::Left,
+            },
+            GameEvent::FruitEaten { snake: 0, pos },
+            GameEvent::SnakeGrew {
+                snake: 0,
+                length: 5,
+            },
+            GameEvent::FruitSpawned { pos: IVec2::ZERO },
+            GameEvent::SnakeDied { snake: 1, cause },
+            GameEvent::GameOver,
+        ]);
+
+        assert_eq!(
+            sent::<SnakeTurned>(&app),
+            [SnakeTurned {
+                snake: 1,
+                direction: Direction::Left
+            }]
+        );
+        assert_eq!(
+            sent::<FruitEaten>(&app),
+            [FruitEaten {
+                snake: 0,
+                fruit,
+                pos
+            }]
+        );
+        assert_eq!(
+            sent::<SnakeGrew>(&app),
+            [SnakeGrew {
+                snake: 0,
+                length: 5
+            }]
+        );
+        assert_eq!(sent::<SnakeDied>(&app), [SnakeDied { snake: 1, cause }]);
+        assert_eq!(sent::<GameEnded>(&app), [GameEnded { won: false }]);
+    }
+
+    #[test]
+    fn winning_ends_the_game_won() {
+        let (app, _) = app(vec![GameEvent::Won, GameEvent::GameOver]);
+        assert_eq!(sent::<GameEnded>(&app), [GameEnded { won: true }]);
+        assert_eq!(sent::<SnakeDied>(&app), []);
+    }
+}
@@ -0,0 +1,386 @@
+//! Bevy side of the game. Feeds input into the [`GameState`] simulation and
+//! keeps the sprites in sync with it.
+
+use std::time::Duration;
+
+use bevy::{
+    ecs::{schedule::ShouldRun, system::EntityCommands},
+    prelude::*,
+    time::FixedTimestep,
+};
+
+use crate::{
+    bindings::Action,
+    board::{FRUIT_Z, SNAKE_Z},
+    config::GameConfig,
+    controls::{snake_input_system, TurnQueues},
+    events::{GameEnded, GameEvents},
+    hud::elapsed,
+    interpolation::Tween,
+    replay::ReplayRecorder,
+    sim::{Direction, GameEvent, GameState, Snake},
+    sprites::SNAKE_ATLAS,
+    AppState,
+};
+
+/// Plugin running the snake game itself
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        let config = app
+            .world
+            .get_resource_or_insert_with(GameConfig::default)
+            .clone();
+
+        app.insert_resource(GameState::new(config.rules(), 0))
+            .init_resource::<Score>()
+            .init_resource::<TurnQueues>()
+            .init_resource::<ReplayRecorder>()
+            .init_resource::<Victory>()
+            .add_startup_system(setup_system)
+            .add_system_set(
+                SystemSet::on_enter(AppState::Playing)
+                    .with_system(clear_board_system)
+                    .with_system(new_game_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(snake_input_system.label(TickLabel::Input))
+                    .with_system(pause_system),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(
+                        FixedTimestep::step(config.tick_step())
+                            .with_label(TICK_TIMESTEP)
+                            .chain(playing_criteria),
+                    )
+                    .after(TickLabel::Input)
+                    .with_system(game_tick_system.label(TickLabel::Step))
+                    .with_system(end_game_system.after(TickLabel::Step))
+                    .with_system(
+                        sync_snake_system
+                            .label(TickLabel::Sync)
+                            .after(TickLabel::Step),
+                    ),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, grid_transform_system);
+    }
+}
+
+/// Label of the fixed timestep the game ticks in
+pub const TICK_TIMESTEP: &str = "game_tick";
+
+/// The parts of a game tick, in the order they run. Everything that changes
+/// the game happens in [`TickLabel::Step`], inside the fixed timestep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemLabel)]
+pub enum TickLabel {
+    /// Queueing the turns of the players, every frame
+    Input,
+    /// Taking the next turns and advancing the simulation
+    Step,
+    /// Updating the entities from the simulation
+    Sync,
+}
+
+/// Only let the fixed tick through while a game is running
+fn playing_criteria(In(tick): In<ShouldRun>, state: Res<State<AppState>>) -> ShouldRun {
+    if *state.current() == AppState::Playing {
+        tick
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Setup the camera
+pub fn setup_system(mut commands: Commands, config: Res<GameConfig>) {
+    commands.spawn_bundle(Camera2dBundle {
+        camera_2d: Camera2d {
+            clear_color: bevy::core_pipeline::clear_color::ClearColorConfig::Custom(
+                config.colors.background,
+            ),
+        },
+        transform: Transform::from_xyz(0.0, 0.0, 10.0),
+        ..Default::default()
+    });
+}
+
+/// Remove everything left over from the last game
+pub fn clear_board_system(
+    mut commands: Commands,
+    snake_parts: Query<Entity, With<SnakePart>>,
+    fruits: Query<Entity, With<Fruit>>,
+) {
+    for entity in snake_parts.iter().chain(fruits.iter()) {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Start a fresh game
+pub fn new_game_system(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut state: ResMut<GameState>,
+    mut score: ResMut<Score>,
+    mut queues: ResMut<TurnQueues>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    // Without a configured seed every game gets its own, shown at the end so
+    // the game can be played again
+    let seed = config.seed.unwrap_or_else(rand::random);
+    *state = GameState::new(config.rules(), seed);
+    *score = Score(vec![0; state.snakes().len()]);
+    *recorder = ReplayRecorder::new(&config, seed);
+    *queues = TurnQueues::new(state.snakes().len());
+
+    spawn_game_entities(&mut commands, &config, &state);
+}
+
+/// Spawn the snakes and the fruit of a game that just started
+pub fn spawn_game_entities(commands: &mut Commands, config: &GameConfig, state: &GameState) {
+    // Spawn players
+    for (idx, snake) in state.snakes().iter().enumerate() {
+        create_snake_part(commands, config, idx, snake.head()).insert(SnakeHead {
+            snake: idx,
+            tail: Vec::new(),
+        });
+    }
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: config.colors.fruit,
+                custom_size: Some(Vec2::splat(config.cell_size)),
+                ..Default::default()
+            },
+            visibility: Visibility {
+                is_visible: state.fruit().is_some(),
+            },
+            transform: Transform::from_xyz(0.0, 0.0, FRUIT_Z),
+            ..Default::default()
+        })
+        .insert(GridPos(state.fruit().unwrap_or_default()))
+        .insert(Fruit);
+}
+
+/// Create a part of the snake with the given index
+pub fn create_snake_part<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    config: &GameConfig,
+    snake: usize,
+    cell: IVec2,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut ent = commands.spawn_bundle(SpriteSheetBundle {
+        sprite: TextureAtlasSprite {
+            color: config.colors.snake(snake),
+            custom_size: Some(Vec2::splat(config.cell_size)),
+            ..Default::default()
+        },
+        texture_atlas: SNAKE_ATLAS.typed(),
+        transform: Transform::from_xyz(0.0, 0.0, SNAKE_Z),
+        ..Default::default()
+    });
+    ent.insert(GridPos(cell))
+        .insert(Tween::at(cell))
+        .insert(SnakePart);
+    ent
+}
+
+/// Advance the simulation by one tick with the next queued turns
+pub fn game_tick_system(
+    mut state: ResMut<GameState>,
+    mut queues: ResMut<TurnQueues>,
+    mut score: ResMut<Score>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut events: GameEvents,
+) {
+    let inputs = queues.pop_all();
+    recorder.record(state.tick(), &inputs);
+    events.send(&step_game(&mut state, &mut score, &inputs));
+}
+
+/// Leave the game once it is over, for the victory screen if it was won
+pub fn end_game_system(
+    mut events: EventReader<GameEnded>,
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    mut victory: ResMut<Victory>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    for event in events.iter() {
+        let next = if event.won {
+            *victory = Victory {
+                duration: elapsed(&state, &config),
+                moves: state.snakes().iter().map(Snake::moves).sum(),
+            };
+            AppState::Victory
+        } else {
+            AppState::GameOver
+        };
+        // A second game over in the same frame is already queued
+        let _ = app_state.set(next);
+    }
+}
+
+/// Step the simulation and count the fruits eaten into the score
+pub fn step_game(
+    state: &mut GameState,
+    score: &mut Score,
+    inputs: &[Option<Direction>],
+) -> Vec<GameEvent> {
+    let events = state.step(inputs);
+    for event in &events {
+        if let GameEvent::FruitEaten { snake, .. } = *event {
+            if let Some(score) = score.0.get_mut(snake) {
+                *score += 1;
+            }
+        }
+    }
+    events
+}
+
+/// Fruits, kept apart from the snake parts so both can be changed at once
+type FruitFilter = (With<Fruit>, Without<SnakePart>);
+
+/// Move the snake parts and fruits to the cells the simulation has them in
+pub fn sync_snake_system(
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    mut snake_heads: Query<(&mut GridPos, &mut SnakeHead), With<SnakePart>>,
+    mut snake_parts: Query<&mut GridPos, (With<SnakePart>, Without<SnakeHead>)>,
+    mut fruits: Query<(&mut GridPos, &mut Visibility), FruitFilter>,
+    mut commands: Commands,
+) {
+    for (mut grid_pos, mut snake_head) in snake_heads.iter_mut() {
+        let snake = match state.snakes().get(snake_head.snake) {
+            Some(snake) => snake,
+            None => continue,
+        };
+
+        let mut body = snake.body();
+        if let Some(head) = body.next() {
+            grid_pos.0 = head;
+        }
+
+        while snake_head.tail.len() > body.len() {
+            if let Some(part) = snake_head.tail.pop() {
+                commands.entity(part).despawn();
+            }
+        }
+
+        for (idx, cell) in body.enumerate() {
+            match snake_head.tail.get(idx) {
+                Some(&part) => {
+                    if let Ok(mut part) = snake_parts.get_mut(part) {
+                        part.0 = cell;
+                    }
+                }
+                None => {
+                    let part =
+                        create_snake_part(&mut commands, &config, snake_head.snake, cell).id();
+                    snake_head.tail.push(part);
+                }
+            }
+        }
+    }
+
+    // The fruit is gone once the snakes filled the board
+    for (mut fruit, mut visibility) in fruits.iter_mut() {
+        match state.fruit() {
+            Some(pos) => fruit.0 = pos,
+            None => visibility.is_visible = false,
+        }
+    }
+}
+
+/// Entities that changed their cell and are not drawn between cells
+type MovedFilter = (Changed<GridPos>, Without<Tween>);
+
+/// Derive the translation of everything on the board from its [`GridPos`],
+/// except for the snake parts moving between cells
+pub fn grid_transform_system(
+    config: Res<GameConfig>,
+    mut query: Query<(&GridPos, &mut Transform), MovedFilter>,
+) {
+    for (grid_pos, mut transform) in query.iter_mut() {
+        let translation = cell_to_translation(&config, grid_pos.0);
+        transform.translation.x = translation.x;
+        transform.translation.y = translation.y;
+    }
+}
+
+/// Pause or restart the running game
+pub fn pause_system(
+    mut input: ResMut<Input<KeyCode>>,
+    config: Res<GameConfig>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if config
+        .bindings
+        .clear_just_pressed(&mut input, Action::Pause)
+    {
+        let _ = app_state.push(AppState::Paused);
+    } else if config
+        .bindings
+        .clear_just_pressed(&mut input, Action::Restart)
+    {
+        let _ = app_state.restart();
+    }
+}
+
+/// Convert a board cell to a translation with the board centered on the origin
+pub fn cell_to_translation(config: &GameConfig, cell: IVec2) -> Vec3 {
+    let center = Vec2::new(
+        config.board_width as f32 - 1.0,
+        config.board_height as f32 - 1.0,
+    ) / 2.0;
+    let pos = (cell.as_vec2() - center) * config.cell_size;
+    Vec3::new(pos.x, pos.y, 0.0)
+}
+
+/// The cell of an entity on the board. The [`Transform`] is derived from it,
+/// see [`Tween`] for the snake parts
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridPos(pub IVec2);
+
+/// How the last won game went
+#[derive(Debug, Clone, Default)]
+pub struct Victory {
+    /// In game time it took to fill the board
+    pub duration: Duration,
+    /// Cells moved by all snakes together
+    pub moves: u64,
+}
+
+/// Fruits eaten in the current game, one entry per snake
+#[derive(Debug, Clone, Default)]
+pub struct Score(pub Vec<u32>);
+
+/// The snakes head
+#[derive(Component, Debug, Default)]
+pub struct SnakeHead {
+    /// Index of the snake in the [`GameState`]
+    snake: usize,
+    tail: Vec<Entity>,
+}
+
+impl SnakeHead {
+    /// Index of the snake in the [`GameState`]
+    pub fn snake(&self) -> usize {
+        self.snake
+    }
+
+    /// The other parts of the snake, from the neck to the end of the tail
+    pub fn tail(&self) -> &[Entity] {
+        &self.tail
+    }
+}
+
+/// Any part of the snake
+#[derive(Component, Debug)]
+pub struct SnakePart;
+
+/// A fruit for the snake to collect
+#[derive(Component, Debug)]
+pub struct Fruit;
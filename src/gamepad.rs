@@ -0,0 +1,343 @@
+//! Playing with gamepads.
+//!
+//! Every gamepad that is plugged in is given to the first player without
+//! one. The D-pad and the left stick turn the snake, the shoulder buttons
+//! turn it relative to where it is heading and South is the single switch.
+//! Start pauses and Select restarts. [`PadInput`] only looks at
+//! [`GamepadEvent`]s, so it works the same with events that did not come from
+//! real hardware.
+
+use bevy::prelude::*;
+
+use crate::{
+    bindings::{Action, Binding},
+    config::{GameConfig, MAX_PLAYERS},
+    controls::TurnQueues,
+    game::TickLabel,
+    sim::{Direction, GameState},
+    AppState,
+};
+
+/// Value from which a button counts as pressed
+const PRESS_THRESHOLD: f32 = 0.75;
+/// Value below which a pressed button counts as released again
+const RELEASE_THRESHOLD: f32 = 0.65;
+
+/// Plugin turning gamepad events into turns and menu actions
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        let deadzone = app
+            .world
+            .get_resource_or_insert_with(GameConfig::default)
+            .gamepad_deadzone;
+
+        app.insert_resource(PadInput::new(deadzone))
+            .add_system(gamepad_system.label(TickLabel::Input));
+    }
+}
+
+/// The direction a stick points in, `None` while it is inside the deadzone.
+/// Diagonals go to the axis the stick is pushed further along
+pub fn stick_direction(x: f32, y: f32, deadzone: f32) -> Option<Direction> {
+    if x.abs().max(y.abs()) < deadzone {
+        None
+    } else if x.abs() > y.abs() {
+        Some(if x > 0.0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        })
+    } else {
+        Some(if y > 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        })
+    }
+}
+
+/// The action a button stands for
+pub fn button_action(button: GamepadButtonType) -> Option<Action> {
+    match button {
+        GamepadButtonType::DPadUp => Some(Action::Up),
+        GamepadButtonType::DPadDown => Some(Action::Down),
+        GamepadButtonType::DPadLeft => Some(Action::Left),
+        GamepadButtonType::DPadRight => Some(Action::Right),
+        GamepadButtonType::LeftTrigger => Some(Action::TurnLeft),
+        GamepadButtonType::RightTrigger | GamepadButtonType::South => Some(Action::TurnRight),
+        GamepadButtonType::Start => Some(Action::Pause),
+        GamepadButtonType::Select => Some(Action::Restart),
+        _ => None,
+    }
+}
+
+/// State of a single gamepad
+#[derive(Debug, Clone)]
+struct Pad {
+    gamepad: Gamepad,
+    /// Position of the left stick
+    stick: Vec2,
+    /// Direction the stick pointed in the last time it was moved
+    stick_direction: Option<Direction>,
+    /// Buttons held down
+    pressed: Vec<GamepadButtonType>,
+}
+
+/// Which player each gamepad belongs to and what the pads are doing
+#[derive(Debug, Clone)]
+pub struct PadInput {
+    /// The gamepad of every player, by index
+    players: [Option<Pad>; MAX_PLAYERS],
+    /// How far a stick has to be pushed to turn the snake, from 0 to 1
+    pub deadzone: f32,
+}
+
+impl PadInput {
+    pub fn new(deadzone: f32) -> Self {
+        Self {
+            players: Default::default(),
+            deadzone,
+        }
+    }
+
+    /// The player the gamepad is assigned to
+    pub fn player(&self, gamepad: Gamepad) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|pad| matches!(pad, Some(pad) if pad.gamepad == gamepad))
+    }
+
+    /// The gamepad assigned to the player
+    pub fn gamepad(&self, player: usize) -> Option<Gamepad> {
+        self.players.get(player)?.as_ref().map(|pad| pad.gamepad)
+    }
+
+    /// Update the state of the pads. Returns the action of a player the
+    /// event triggered, if any.
+    ///
+    /// Buttons trigger when they are pressed and the stick when it is pushed
+    /// into a new direction, holding them down does nothing more.
+    pub fn handle(&mut self, event: &GamepadEvent) -> Option<Binding> {
+        let gamepad = event.gamepad;
+        match event.event_type {
+            GamepadEventType::Connected => {
+                if self.player(gamepad).is_none() {
+                    if let Some(slot) = self.players.iter_mut().find(|pad| pad.is_none()) {
+                        *slot = Some(Pad {
+                            gamepad,
+                            stick: Vec2::ZERO,
+                            stick_direction: None,
+                            pressed: Vec::new(),
+                        });
+                    }
+                }
+                None
+            }
+            GamepadEventType::Disconnected => {
+                if let Some(player) = self.player(gamepad) {
+                    self.players[player] = None;
+                }
+                None
+            }
+            GamepadEventType::ButtonChanged(button, value) => {
+                let player = self.player(gamepad)?;
+                let pad = self.players[player].as_mut()?;
+                let held = pad.pressed.contains(&button);
+                if !held && value >= PRESS_THRESHOLD {
+                    pad.pressed.push(button);
+                    let action = button_action(button)?;
+                    Some(Binding { player, action })
+                } else {
+                    if held && value < RELEASE_THRESHOLD {
+                        pad.pressed.retain(|&other| other != button);
+                    }
+                    None
+                }
+            }
+            GamepadEventType::AxisChanged(axis, value) => {
+                let player = self.player(gamepad)?;
+                let deadzone = self.deadzone;
+                let pad = self.players[player].as_mut()?;
+                match axis {
+                    GamepadAxisType::LeftStickX => pad.stick.x = value,
+                    GamepadAxisType::LeftStickY => pad.stick.y = value,
+                    _ => return None,
+                }
+
+                let direction = stick_direction(pad.stick.x, pad.stick.y, deadzone);
+                if direction == pad.stick_direction {
+                    return None;
+                }
+                pad.stick_direction = direction;
+                Some(Binding {
+                    player,
+                    action: direction?.into(),
+                })
+            }
+        }
+    }
+}
+
+/// Assign plugged in gamepads to players and let them play and pause
+pub fn gamepad_system(
+    mut events: EventReader<GamepadEvent>,
+    mut pads: ResMut<PadInput>,
+    mut queues: ResMut<TurnQueues>,
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    for event in events.iter() {
+        let binding = pads.handle(event);
+        if event.event_type == GamepadEventType::Connected {
+            match pads.player(event.gamepad) {
+                Some(player) => info!("Gamepad {} plays P{}", event.gamepad.id, player + 1),
+                None => info!("Gamepad {} is left over", event.gamepad.id),
+            }
+        }
+
+        let binding = match binding {
+            Some(binding) => binding,
+            None => continue,
+        };
+        let current = *app_state.current();
+        // Transitions queued in the same frame already are ignored
+        let _ = match (binding.action, current) {
+            (Action::Pause, AppState::Playing) => app_state.push(AppState::Paused),
+            (Action::Pause, AppState::Paused) => app_state.pop(),
+            (Action::Pause, AppState::MainMenu) => app_state.replace(AppState::Playing),
+            (Action::Restart, AppState::Playing) => app_state.restart(),
+            (Action::Restart, AppState::Paused | AppState::GameOver | AppState::Victory) => {
+                app_state.replace(AppState::Playing)
+            }
+            (_, AppState::Playing) => {
+                queues.push_action(&state, &config, binding);
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected(id: usize) -> GamepadEvent {
+        GamepadEvent::new(Gamepad::new(id), GamepadEventType::Connected)
+    }
+
+    fn disconnected(id: usize) -> GamepadEvent {
+        GamepadEvent::new(Gamepad::new(id), GamepadEventType::Disconnected)
+    }
+
+    fn button(id: usize, button: GamepadButtonType, value: f32) -> GamepadEvent {
+        let event = GamepadEventType::ButtonChanged(button, value);
+        GamepadEvent::new(Gamepad::new(id), event)
+    }
+
+    fn axis(id: usize, axis: GamepadAxisType, value: f32) -> GamepadEvent {
+        let event = GamepadEventType::AxisChanged(axis, value);
+        GamepadEvent::new(Gamepad::new(id), event)
+    }
+
+    fn binding(player: usize, action: Action) -> Option<Binding> {
+        Some(Binding { player, action })
+    }
+
+    #[test]
+    fn gamepads_go_to_the_first_free_player() {
+        let mut pads = PadInput::new(0.5);
+        for id in [3, 7, 3] {
+            assert_eq!(pads.handle(&connected(id)), None);
+        }
+        assert_eq!(pads.player(Gamepad::new(3)), Some(0));
+        assert_eq!(pads.player(Gamepad::new(7)), Some(1));
+        assert_eq!(pads.gamepad(2), None);
+
+        // A pad plugged in again takes the first free slot
+        pads.handle(&disconnected(3));
+        assert_eq!(pads.player(Gamepad::new(3)), None);
+        assert_eq!(pads.gamepad(0), None);
+        pads.handle(&connected(9));
+        assert_eq!(pads.gamepad(0), Some(Gamepad::new(9)));
+        pads.handle(&connected(3));
+        assert_eq!(pads.player(Gamepad::new(3)), Some(2));
+    }
+
+    #[test]
+    fn gamepads_beyond_the_players_are_left_over() {
+        let mut pads = PadInput::new(0.5);
+        for id in 0..=MAX_PLAYERS {
+            pads.handle(&connected(id));
+        }
+        assert_eq!(pads.player(Gamepad::new(MAX_PLAYERS)), None);
+        let event = button(MAX_PLAYERS, GamepadButtonType::Start, 1.0);
+        assert_eq!(pads.handle(&event), None);
+    }
+
+    #[test]
+    fn buttons_trigger_once_until_released() {
+        let mut pads = PadInput::new(0.5);
+        pads.handle(&connected(0));
+        pads.handle(&connected(1));
+        let dpad = GamepadButtonType::DPadLeft;
+
+        assert_eq!(pads.handle(&button(1, dpad, 0.7)), None);
+        assert_eq!(pads.handle(&button(1, dpad, 0.8)), binding(1, Action::Left));
+        assert_eq!(pads.handle(&button(1, dpad, 1.0)), None);
+        // Between the thresholds the button stays held
+        assert_eq!(pads.handle(&button(1, dpad, 0.7)), None);
+        assert_eq!(pads.handle(&button(1, dpad, 0.8)), None);
+
+        assert_eq!(pads.handle(&button(1, dpad, 0.6)), None);
+        assert_eq!(pads.handle(&button(1, dpad, 0.8)), binding(1, Action::Left));
+    }
+
+    #[test]
+    fn unknown_gamepads_and_buttons_do_nothing() {
+        let mut pads = PadInput::new(0.5);
+        assert_eq!(pads.handle(&button(0, GamepadButtonType::South, 1.0)), None);
+        pads.handle(&connected(0));
+        assert_eq!(pads.handle(&button(0, GamepadButtonType::Mode, 1.0)), None);
+        assert_eq!(
+            pads.handle(&button(0, GamepadButtonType::South, 1.0)),
+            binding(0, Action::TurnRight)
+        );
+    }
+
+    #[test]
+    fn stick_direction_resolves_diagonals_and_the_deadzone() {
+        assert_eq!(stick_direction(0.3, -0.4, 0.5), None);
+        assert_eq!(stick_direction(0.0, 0.5, 0.5), Some(Direction::Up));
+        assert_eq!(stick_direction(0.7, 0.6, 0.5), Some(Direction::Right));
+        assert_eq!(stick_direction(-0.6, 0.7, 0.5), Some(Direction::Up));
+        assert_eq!(stick_direction(-0.9, -0.2, 0.5), Some(Direction::Left));
+        assert_eq!(stick_direction(0.4, -0.6, 0.5), Some(Direction::Down));
+    }
+
+    #[test]
+    fn the_stick_triggers_only_for_new_directions() {
+        let mut pads = PadInput::new(0.5);
+        pads.handle(&connected(0));
+        let (x, y) = (GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY);
+
+        assert_eq!(pads.handle(&axis(0, x, 0.3)), None);
+        assert_eq!(pads.handle(&axis(0, x, 0.6)), binding(0, Action::Right));
+        assert_eq!(pads.handle(&axis(0, x, 0.9)), None);
+        assert_eq!(pads.handle(&axis(0, y, 0.5)), None);
+        assert_eq!(pads.handle(&axis(0, y, 0.95)), binding(0, Action::Up));
+
+        // Back in the deadzone and out again in the same direction
+        pads.handle(&axis(0, x, 0.0));
+        assert_eq!(pads.handle(&axis(0, y, 0.1)), None);
+        assert_eq!(pads.handle(&axis(0, y, 0.8)), binding(0, Action::Up));
+
+        assert_eq!(
+            pads.handle(&axis(0, GamepadAxisType::RightStickX, 1.0)),
+            None
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Playing games without a window, e.g. to check the rules in CI
+
+use crate::{
+    bot::greedy_direction,
+    config::GameConfig,
+    replay::Replay,
+    sim::{Direction, GameEvent, GameState, Rules},
+};
+
+/// How a game played without a window ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameSummary {
+    pub seed: u64,
+    /// Ticks until the game ended or was cut off
+    pub ticks: u64,
+    /// Fruits eaten by each snake
+    pub scores: Vec<u32>,
+    /// Final length of each snake
+    pub lengths: Vec<usize>,
+    /// Whether the game was cut off before every snake died
+    pub timed_out: bool,
+    /// Whether the snakes filled the whole board
+    pub won: bool,
+}
+
+/// Let the bot play `games` games with the given configuration. With a seed
+/// in the configuration the games use it and the seeds counting up from it
+pub fn run(config: &GameConfig, games: u32) -> Vec<GameSummary> {
+    (0..games)
+        .map(|idx| {
+            let seed = match config.seed {
+                Some(seed) => seed.wrapping_add(u64::from(idx)),
+                None => rand::random(),
+            };
+            play(config, seed)
+        })
+        .collect()
+}
+
+/// Let the bot play a single game
+pub fn play(config: &GameConfig, seed: u64) -> GameSummary {
+    // A bot running in circles would never end the game on its own
+    let max_ticks = (config.board_width * config.board_height) as u64 * 100;
+    simulate(config.rules(), seed, max_ticks, |state, snake| {
+        greedy_direction(state, snake)
+    })
+}
+
+/// Play a recorded game to its end
+pub fn play_replay(replay: &Replay) -> GameSummary {
+    simulate(
+        replay.rules.clone(),
+        replay.seed,
+        replay.ticks,
+        |state, snake| {
+            replay
+                .inputs_at(state.tick(), state.snakes().len())
+                .get(snake)
+                .copied()
+                .flatten()
+        },
+    )
+}
+
+/// Run a game until it is over or `max_ticks` passed, asking `input` for the
+/// direction of every snake on every tick
+fn simulate(
+    rules: Rules,
+    seed: u64,
+    max_ticks: u64,
+    mut input: impl FnMut(&GameState, usize) -> Option<Direction>,
+) -> GameSummary {
+    let mut state = GameState::new(rules, seed);
+    let mut scores = vec![0; state.snakes().len()];
+
+    while !state.is_over() && state.tick() < max_ticks {
+        let inputs = (0..state.snakes().len())
+            .map(|snake| input(&state, snake))
+            .collect::<Vec<_>>();
+        for event in state.step(&inputs) {
+            if let GameEvent::FruitEaten { snake, .. } = event {
+                scores[snake] += 1;
+            }
+        }
+    }
+
+    GameSummary {
+        seed,
+        ticks: state.tick(),
+        scores,
+        lengths: state.snakes().iter().map(|snake| snake.length()).collect(),
+        timed_out: !state.is_over(),
+        won: state.is_won(),
+    }
+}
@@ -0,0 +1,385 @@
+//! High score table saved in the user's data directory
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::GameConfig, game::Score, hud::elapsed, sim::GameState, AppState};
+
+/// Version of the file format written by this build
+pub const HIGH_SCORE_VERSION: u32 = 1;
+/// Number of entries kept in the table
+pub const MAX_HIGH_SCORES: usize = 10;
+
+/// Plugin loading the high scores at startup and adding every finished game
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        let path = HighScores::default_path();
+        let (high_scores, path) = match path.as_deref().and_then(HighScores::load_or_recover) {
+            Some(high_scores) => (high_scores, path),
+            // Never save over a file that could not be read
+            None => (HighScores::default(), None),
+        };
+
+        app.insert_resource(high_scores)
+            .insert_resource(HighScorePath(path))
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(record_high_score_system),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Victory).with_system(record_high_score_system),
+            );
+    }
+}
+
+/// Where the high scores are saved, `None` if there is no data directory
+#[derive(Debug, Clone)]
+pub struct HighScorePath(pub Option<PathBuf>);
+
+/// A single finished game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub length: usize,
+    pub duration: Duration,
+    pub seed: u64,
+    pub mode: String,
+}
+
+impl Default for HighScoreEntry {
+    fn default() -> Self {
+        Self {
+            name: String::from("Player"),
+            score: 0,
+            length: 1,
+            duration: Duration::ZERO,
+            seed: 0,
+            mode: String::from("classic"),
+        }
+    }
+}
+
+/// The best games, highest score first
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighScores {
+    pub version: u32,
+    pub entries: Vec<HighScoreEntry>,
+}
+
+/// Only the version of a high score file, read before the rest of it
+#[derive(Debug, Deserialize)]
+struct VersionProbe {
+    #[serde(default)]
+    version: u32,
+}
+
+/// Reasons a high score file could not be used
+#[derive(Debug)]
+pub enum HighScoreError {
+    Io(io::Error),
+    Parse(ron::Error),
+    /// Written by a newer build of the game
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for HighScoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HighScoreError::Io(err) => write!(f, "io error: {}", err),
+            HighScoreError::Parse(err) => write!(f, "invalid high score file: {}", err),
+            HighScoreError::UnsupportedVersion(version) => {
+                write!(f, "unsupported high score file version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HighScoreError {}
+
+impl From<io::Error> for HighScoreError {
+    fn from(err: io::Error) -> Self {
+        HighScoreError::Io(err)
+    }
+}
+
+impl From<ron::Error> for HighScoreError {
+    fn from(err: ron::Error) -> Self {
+        HighScoreError::Parse(err)
+    }
+}
+
+impl HighScores {
+    /// `highscores.ron` in the data directory of the user
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("bevy_snake").join("highscores.ron"))
+    }
+
+    /// Parse a high score file of this or any older version
+    pub fn parse(content: &str) -> Result<Self, HighScoreError> {
+        let probe: VersionProbe = ron::from_str(content)?;
+        if probe.version > HIGH_SCORE_VERSION {
+            return Err(HighScoreError::UnsupportedVersion(probe.version));
+        }
+
+        // Older versions only lack fields, which fall back to their defaults
+        let mut high_scores: Self = ron::from_str(content)?;
+        high_scores.version = HIGH_SCORE_VERSION;
+        Ok(high_scores)
+    }
+
+    /// Read the high scores from a file. A missing file is an empty table
+    pub fn load(path: &Path) -> Result<Self, HighScoreError> {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Read the high scores. A broken file or one from a newer build is moved
+    /// out of the way so it is not overwritten, and the table starts empty.
+    /// Earlier backups are kept, see [`HighScores::backup_path`].
+    /// Returns `None` if the file could not be read or moved away, it must not
+    /// be saved over then
+    pub fn load_or_recover(path: &Path) -> Option<Self> {
+        let err = match Self::load(path) {
+            Ok(high_scores) => return Some(high_scores),
+            Err(err) => err,
+        };
+        warn!(
+            "Could not load high scores from {}: {}",
+            path.display(),
+            err
+        );
+        if let HighScoreError::Io(_) = err {
+            return None;
+        }
+
+        let backup = Self::backup_path(path);
+        match fs::rename(path, &backup) {
+            Ok(()) => Some(Self::default()),
+            Err(err) => {
+                warn!(
+                    "Could not back up high scores to {}: {}",
+                    backup.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// The first of `highscores.ron.bak`, `highscores.ron.bak.2`,
+    /// `highscores.ron.bak.3` and so on that does not exist yet
+    pub fn backup_path(path: &Path) -> PathBuf {
+        let mut backup = path.with_extension("ron.bak");
+        let mut number = 1;
+        while backup.exists() {
+            number += 1;
+            backup = path.with_extension(format!("ron.bak.{}", number));
+        }
+        backup
+    }
+
+    /// Write the high scores to a file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), HighScoreError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let high_scores = Self {
+            version: HIGH_SCORE_VERSION,
+            entries: self.entries.clone(),
+        };
+        let content = ron::ser::to_string_pretty(&high_scores, Default::default())?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Add an entry if it makes it into the table. Returns its rank starting at 0
+    pub fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| {
+                entry.score > other.score
+                    || (entry.score == other.score && entry.duration < other.duration)
+            })
+            .unwrap_or(self.entries.len());
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_HIGH_SCORES);
+        Some(rank)
+    }
+}
+
+/// Name the entries of this user are saved with
+pub fn player_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| String::from("Player"))
+}
+
+/// Put the game that just ended into the high scores and save them
+pub fn record_high_score_system(
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    score: Res<Score>,
+    path: Res<HighScorePath>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    let name = player_name();
+    for (idx, snake) in state.snakes().iter().enumerate() {
+        high_scores.insert(HighScoreEntry {
+            name: if state.snakes().len() > 1 {
+                format!("{} P{}", name, idx + 1)
+            } else {
+                name.clone()
+            },
+            score: score.0.get(idx).copied().unwrap_or_default(),
+            length: snake.length(),
+            duration: elapsed(&state, &config),
+            seed: state.seed(),
+            mode: String::from(state.rules().mode.name()),
+        });
+    }
+
+    if let Some(path) = &path.0 {
+        if let Err(err) = high_scores.save(path) {
+            warn!("Could not save high scores to {}: {}", path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: u32, secs: u64) -> HighScoreEntry {
+        HighScoreEntry {
+            score,
+            duration: Duration::from_secs(secs),
+            ..Default::default()
+        }
+    }
+
+    /// An empty directory of its own for a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bevy_snake_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parse_fills_fields_missing_in_version_0() {
+        let high_scores = HighScores::parse(r#"(entries: [(name: "ada", score: 7)])"#).unwrap();
+        assert_eq!(high_scores.version, HIGH_SCORE_VERSION);
+        assert_eq!(
+            high_scores.entries,
+            [HighScoreEntry {
+                name: String::from("ada"),
+                score: 7,
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        let err = HighScores::parse("not a high score table").unwrap_err();
+        assert!(matches!(err, HighScoreError::Parse(_)));
+    }
+
+    #[test]
+    fn parse_rejects_newer_versions() {
+        let content = format!("(version: {}, entries: [])", HIGH_SCORE_VERSION + 1);
+        let err = HighScores::parse(&content).unwrap_err();
+        assert!(matches!(
+            err,
+            HighScoreError::UnsupportedVersion(version) if version == HIGH_SCORE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn insert_ranks_by_score_then_duration() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(entry(5, 30)), Some(0));
+        assert_eq!(high_scores.insert(entry(9, 30)), Some(0));
+        assert_eq!(high_scores.insert(entry(5, 20)), Some(1));
+        assert_eq!(high_scores.insert(entry(5, 40)), Some(3));
+
+        let scores = high_scores.entries.iter().map(|entry| entry.score);
+        assert_eq!(scores.collect::<Vec<_>>(), [9, 5, 5, 5]);
+        assert_eq!(high_scores.entries[1].duration, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn insert_keeps_only_the_best_entries() {
+        let mut high_scores = HighScores::default();
+        for score in 1..=MAX_HIGH_SCORES as u32 {
+            high_scores.insert(entry(score, 10));
+        }
+        assert_eq!(high_scores.insert(entry(0, 10)), None);
+        assert_eq!(high_scores.entries.len(), MAX_HIGH_SCORES);
+
+        assert_eq!(high_scores.insert(entry(100, 10)), Some(0));
+        assert_eq!(high_scores.entries.len(), MAX_HIGH_SCORES);
+        assert_eq!(high_scores.entries.last().unwrap().score, 2);
+    }
+
+    #[test]
+    fn recover_backs_up_broken_files() {
+        let dir = temp_dir("broken_high_scores");
+        let path = dir.join("highscores.ron");
+        fs::write(&path, "not a high score table").unwrap();
+
+        assert_eq!(
+            HighScores::load_or_recover(&path),
+            Some(HighScores::default())
+        );
+        assert!(!path.exists());
+        let backup = fs::read_to_string(dir.join("highscores.ron.bak")).unwrap();
+        assert_eq!(backup, "not a high score table");
+
+        // Another broken file does not replace the first backup
+        for content in ["broken again", "(version: 9999)"] {
+            fs::write(&path, content).unwrap();
+            assert_eq!(
+                HighScores::load_or_recover(&path),
+                Some(HighScores::default())
+            );
+        }
+        let backup = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(backup("highscores.ron.bak"), "not a high score table");
+        assert_eq!(backup("highscores.ron.bak.2"), "broken again");
+        assert_eq!(backup("highscores.ron.bak.3"), "(version: 9999)");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recover_leaves_unreadable_files_alone() {
+        let dir = temp_dir("unreadable_high_scores");
+        // Reading a directory fails with an io error
+        let path = dir.join("highscores.ron");
+        fs::create_dir(&path).unwrap();
+
+        assert_eq!(HighScores::load_or_recover(&path), None);
+        assert!(path.is_dir());
+        assert!(!dir.join("highscores.ron.bak").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
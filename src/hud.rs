@@ -0,0 +1,94 @@
+//! Heads up display with the score, length and time of the running game
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{config::GameConfig, game::Score, menu::UiFont, sim::GameState, AppState};
+
+/// Plugin showing the [`Score`] and other stats while playing
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::Playing).with_system(spawn_hud_system))
+            .add_system_set(SystemSet::on_enter(AppState::Replay).with_system(spawn_hud_system))
+            .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(despawn_hud_system))
+            .add_system(update_hud_system);
+    }
+}
+
+/// The text node of the hud
+#[derive(Component, Debug)]
+pub struct Hud;
+
+/// Show the hud, replacing the one of the last game
+pub fn spawn_hud_system(mut commands: Commands, font: Res<UiFont>, huds: Query<Entity, With<Hud>>) {
+    for hud in huds.iter() {
+        commands.entity(hud).despawn_recursive();
+    }
+
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font.0.clone(),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+        )
+        .insert(Hud);
+}
+
+pub fn despawn_hud_system(mut commands: Commands, huds: Query<Entity, With<Hud>>) {
+    for hud in huds.iter() {
+        commands.entity(hud).despawn_recursive();
+    }
+}
+
+/// Write the current stats into the hud
+pub fn update_hud_system(
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    score: Res<Score>,
+    mut huds: Query<&mut Text, With<Hud>>,
+) {
+    for mut text in huds.iter_mut() {
+        let mut value = String::new();
+        for (idx, snake) in state.snakes().iter().enumerate() {
+            if state.snakes().len() > 1 {
+                value += &format!("P{} ", idx + 1);
+            }
+            value += &format!(
+                "Score: {}  Length: {}{}\n",
+                score.0.get(idx).copied().unwrap_or_default(),
+                snake.length(),
+                if snake.is_alive() { "" } else { "  (dead)" },
+            );
+        }
+        value += &format!("Time: {}", format_duration(elapsed(&state, &config)));
+        text.sections[0].value = value;
+    }
+}
+
+/// In game time passed in the given game
+pub fn elapsed(state: &GameState, config: &GameConfig) -> Duration {
+    Duration::from_secs_f64(state.tick() as f64 * config.tick_step())
+}
+
+/// Format a duration as minutes and seconds
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
@@ -0,0 +1,153 @@
+//! Smooth snake movement between ticks.
+//!
+//! The simulation and the [`GridPos`] of every entity stay on the grid, only
+//! the drawn position follows the tick that is in progress. The head slides
+//! from its last cell into its new one and the end of the tail slides after
+//! it. Parts in between stay where they are: the cells of the body do not
+//! change from one tick to the next, only which part is drawn in them, so
+//! corners keep their shape instead of being cut across.
+
+use bevy::{prelude::*, time::FixedTimesteps};
+
+use crate::{
+    config::GameConfig,
+    game::{cell_to_translation, GridPos, SnakeHead, TICK_TIMESTEP},
+    replay::Playback,
+    sim::{Direction, GameState},
+    AppState,
+};
+
+/// Plugin drawing the snakes between the cells they move through
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TickProgress>()
+            .add_system_to_stage(CoreStage::PostUpdate, tick_progress_system)
+            .add_system_to_stage(CoreStage::PostUpdate, tween_target_system)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                tween_transform_system
+                    .after(tick_progress_system)
+                    .after(tween_target_system),
+            );
+    }
+}
+
+/// How far the game is into the current tick, from 0 to 1
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TickProgress(pub f32);
+
+/// Where a snake part is drawn during the current tick. The cells may lie
+/// outside the board when the part moves across a wrapping edge
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tween {
+    /// The cell the part starts the tick in
+    pub from: IVec2,
+    /// The cell the part ends the tick in, its [`GridPos`]
+    pub to: IVec2,
+}
+
+impl Tween {
+    /// A part resting in a cell
+    pub fn at(cell: IVec2) -> Self {
+        Self {
+            from: cell,
+            to: cell,
+        }
+    }
+
+    /// The direction the part is moving in, if it moves
+    pub fn direction(&self) -> Option<Direction> {
+        match self.to - self.from {
+            IVec2 { x: 0, y: 1 } => Some(Direction::Up),
+            IVec2 { x: 0, y: -1 } => Some(Direction::Down),
+            IVec2 { x: -1, y: 0 } => Some(Direction::Left),
+            IVec2 { x: 1, y: 0 } => Some(Direction::Right),
+            _ => None,
+        }
+    }
+}
+
+/// Follow the fixed timestep while playing and the playback while replaying.
+/// Anywhere else the snakes hold still
+pub fn tick_progress_system(
+    app_state: Res<State<AppState>>,
+    timesteps: Res<FixedTimesteps>,
+    playback: Option<Res<Playback>>,
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    mut progress: ResMut<TickProgress>,
+) {
+    let current = match app_state.current() {
+        AppState::Playing => timesteps
+            .get(TICK_TIMESTEP)
+            .map(|timestep| timestep.overstep_percentage() as f32),
+        AppState::Replay => playback.map(|playback| playback.progress(&state, &config)),
+        _ => None,
+    };
+    if let Some(current) = current {
+        progress.0 = current.clamp(0.0, 1.0);
+    }
+}
+
+/// Start a new tween for every part once the simulation moved the snakes
+pub fn tween_target_system(
+    state: Res<GameState>,
+    heads: Query<(Entity, &SnakeHead)>,
+    mut parts: Query<(&GridPos, &mut Tween)>,
+) {
+    if !state.is_changed() {
+        return;
+    }
+
+    for (head, snake_head) in heads.iter() {
+        let tail = snake_head.tail();
+        let last = tail.last().copied();
+        for entity in std::iter::once(head).chain(tail.iter().copied()) {
+            let (grid_pos, mut tween) = match parts.get_mut(entity) {
+                Ok(part) => part,
+                Err(_) => continue,
+            };
+            let moves = entity == head || Some(entity) == last;
+            *tween = if moves {
+                tween_between(&state, tween.to, grid_pos.0)
+            } else {
+                Tween::at(grid_pos.0)
+            };
+        }
+    }
+}
+
+/// The tween from one cell into a neighbouring cell. Moving across a wrapping
+/// edge starts outside the board, so the part does not fly across the whole
+/// board. Anything else, like several ticks in a single frame, just jumps
+fn tween_between(state: &GameState, from: IVec2, to: IVec2) -> Tween {
+    [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ]
+    .into_iter()
+    .find(|&direction| from != to && state.rules().neighbour(from, direction) == to)
+    .map_or(Tween::at(to), |direction| Tween {
+        from: to - direction.offset(),
+        to,
+    })
+}
+
+/// Draw every tweened part between its cells
+pub fn tween_transform_system(
+    config: Res<GameConfig>,
+    progress: Res<TickProgress>,
+    mut query: Query<(&Tween, &mut Transform)>,
+) {
+    for (tween, mut transform) in query.iter_mut() {
+        let from = cell_to_translation(&config, tween.from);
+        let to = cell_to_translation(&config, tween.to);
+        let translation = from.lerp(to, progress.0);
+        transform.translation.x = translation.x;
+        transform.translation.y = translation.y;
+    }
+}
@@ -0,0 +1,38 @@
+//! Snake game implementation with bevy
+
+pub mod audio;
+pub mod bindings;
+pub mod board;
+pub mod bot;
+pub mod cli;
+pub mod config;
+pub mod controls;
+pub mod events;
+pub mod game;
+pub mod gamepad;
+pub mod headless;
+pub mod highscore;
+pub mod hud;
+pub mod interpolation;
+pub mod menu;
+pub mod pointer;
+pub mod replay;
+pub mod sim;
+pub mod sprites;
+pub mod viewport;
+
+/// The screens the game can be in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    MainMenu,
+    Playing,
+    /// Pushed on top of [`AppState::Playing`]
+    Paused,
+    GameOver,
+    /// The snakes filled the whole board
+    Victory,
+    /// Playing back a recorded game
+    Replay,
+    /// Changing the key bindings
+    Bindings,
+}
@@ -0,0 +1,367 @@
+//! Main menu, pause, game over and victory screens
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    bindings::Action,
+    config::GameConfig,
+    game::{Score, Victory},
+    highscore::HighScores,
+    hud::format_duration,
+    sim::GameState,
+    AppState,
+};
+
+/// Number of high scores listed in the main menu
+const LISTED_HIGH_SCORES: usize = 5;
+
+/// Plugin for the screens around the actual game
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(load_font_system)
+            .add_system_set(
+                SystemSet::on_enter(AppState::MainMenu).with_system(spawn_main_menu_system),
+            )
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(main_menu_system))
+            .add_system_set(
+                SystemSet::on_exit(AppState::MainMenu).with_system(despawn_screen_system),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(spawn_pause_system))
+            .add_system_set(SystemSet::on_update(AppState::Paused).with_system(pause_menu_system))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(despawn_screen_system))
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(spawn_game_over_system),
+            )
+            .add_system_set(SystemSet::on_update(AppState::GameOver).with_system(game_over_system))
+            .add_system_set(
+                SystemSet::on_exit(AppState::GameOver).with_system(despawn_screen_system),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Victory).with_system(spawn_victory_system),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Victory).with_system(game_over_system))
+            .add_system_set(
+                SystemSet::on_exit(AppState::Victory).with_system(despawn_screen_system),
+            );
+    }
+}
+
+/// The font used for all text
+#[derive(Debug, Clone)]
+pub struct UiFont(pub Handle<Font>);
+
+/// Root node of the screen currently shown
+#[derive(Component, Debug)]
+pub struct Screen;
+
+/// Load the font used for all text
+pub fn load_font_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(UiFont(asset_server.load("fonts/DejaVuSans.ttf")));
+}
+
+/// Spawn a centered screen with a title and some lines of help below it
+pub fn spawn_screen(commands: &mut Commands, font: &UiFont, title: &str, lines: &[&str]) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(Screen)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font: font.0.clone(),
+                    font_size: 64.0,
+                    color: Color::WHITE,
+                },
+            ));
+            for line in lines {
+                parent.spawn_bundle(TextBundle::from_section(
+                    *line,
+                    TextStyle {
+                        font: font.0.clone(),
+                        font_size: 28.0,
+                        color: Color::WHITE,
+                    },
+                ));
+            }
+        });
+}
+
+/// Remove the screen of the state that is left
+pub fn despawn_screen_system(mut commands: Commands, screens: Query<Entity, With<Screen>>) {
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+pub fn spawn_main_menu_system(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    high_scores: Res<HighScores>,
+) {
+    let mut lines = vec![
+        String::from("Enter / tap: start"),
+        String::from("B: controls and sound"),
+        String::from("F11: fullscreen"),
+        String::from("Escape: quit"),
+    ];
+    if !high_scores.entries.is_empty() {
+        lines.push(String::new());
+        lines.push(String::from("High scores"));
+    }
+    for (rank, entry) in high_scores
+        .entries
+        .iter()
+        .take(LISTED_HIGH_SCORES)
+        .enumerate()
+    {
+        lines.push(format!(
+            "{}. {}  {} ({}, length {}, {})",
+            rank + 1,
+            entry.name,
+            entry.score,
+            format_duration(entry.duration),
+            entry.length,
+            entry.mode,
+        ));
+    }
+
+    let lines = lines.iter().map(String::as_str).collect::<Vec<_>>();
+    spawn_screen(&mut commands, &font, "Snake", &lines);
+}
+
+/// Whether the screen was just clicked or touched, so menus can be used on
+/// touchscreens without a keyboard
+pub fn tapped(mouse: &Input<MouseButton>, touches: &Touches) -> bool {
+    mouse.just_pressed(MouseButton::Left) || touches.any_just_pressed()
+}
+
+/// Start a game or quit from the main menu
+pub fn main_menu_system(
+    mut input: ResMut<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    mut app_state: ResMut<State<AppState>>,
+    mut exit_event: EventWriter<AppExit>,
+) {
+    if input.clear_just_pressed(KeyCode::Return)
+        || input.clear_just_pressed(KeyCode::Space)
+        || tapped(&mouse, &touches)
+    {
+        let _ = app_state.replace(AppState::Playing);
+    } else if input.clear_just_pressed(KeyCode::B) {
+        let _ = app_state.replace(AppState::Bindings);
+    } else if input.clear_just_pressed(KeyCode::Escape) {
+        exit_event.send(AppExit);
+    }
+}
+
+pub fn spawn_pause_system(mut commands: Commands, font: Res<UiFont>, config: Res<GameConfig>) {
+    spawn_screen(
+        &mut commands,
+        &font,
+        "Paused",
+        &[
+            &format!("{}: resume", config.bindings.describe(Action::Pause)),
+            &format!("{}: restart", config.bindings.describe(Action::Restart)),
+            "Q: main menu",
+        ],
+    );
+}
+
+/// Resume, restart or leave the paused game
+pub fn pause_menu_system(
+    mut input: ResMut<Input<KeyCode>>,
+    config: Res<GameConfig>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if config
+        .bindings
+        .clear_just_pressed(&mut input, Action::Pause)
+    {
+        let _ = app_state.pop();
+    } else if config
+        .bindings
+        .clear_just_pressed(&mut input, Action::Restart)
+    {
+        let _ = app_state.replace(AppState::Playing);
+    } else if input.clear_just_pressed(KeyCode::Q) {
+        // Pause and Restart can not be bound to Q, see `Action::reserved_keys`
+        let _ = app_state.replace(AppState::MainMenu);
+    }
+}
+
+/// The score of every player for the end screens
+fn final_score(score: &Score) -> String {
+    match score.0.as_slice() {
+        [score] => format!("Final score: {}", score),
+        scores => scores
+            .iter()
+            .enumerate()
+            .map(|(idx, score)| format!("P{}: {}", idx + 1, score))
+            .collect::<Vec<_>>()
+            .join("  "),
+    }
+}
+
+pub fn spawn_game_over_system(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    score: Res<Score>,
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+) {
+    spawn_screen(
+        &mut commands,
+        &font,
+        "Game over",
+        &[
+            &final_score(&score),
+            &format!("Seed: {}", state.seed()),
+            &format!(
+                "Enter / {} / tap: play again",
+                config.bindings.describe(Action::Restart)
+            ),
+            "Escape: main menu",
+        ],
+    );
+}
+
+pub fn spawn_victory_system(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    score: Res<Score>,
+    state: Res<GameState>,
+    victory: Res<Victory>,
+    config: Res<GameConfig>,
+) {
+    spawn_screen(
+        &mut commands,
+        &font,
+        "You win!",
+        &[
+            "The snake filled the whole board",
+            &final_score(&score),
+            &format!(
+                "Time: {}  Moves: {}",
+                format_duration(victory.duration),
+                victory.moves
+            ),
+            &format!("Seed: {}", state.seed()),
+            &format!(
+                "Enter / {} / tap: play again",
+                config.bindings.describe(Action::Restart)
+            ),
+            "Escape: main menu",
+        ],
+    );
+}
+
+/// Restart or go back to the main menu after a game ended
+pub fn game_over_system(
+    mut input: ResMut<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    config: Res<GameConfig>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if input.clear_just_pressed(KeyCode::Return)
+        || config
+            .bindings
+            .clear_just_pressed(&mut input, Action::Restart)
+        || tapped(&mouse, &touches)
+    {
+        let _ = app_state.replace(AppState::Playing);
+    } else if input.clear_just_pressed(KeyCode::Escape) {
+        // Restart can not be bound to Escape, see `Action::reserved_keys`
+        let _ = app_state.replace(AppState::MainMenu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::{
+        mouse::MouseButtonInput,
+        touch::{TouchInput, TouchPhase},
+        ButtonState, InputPlugin,
+    };
+
+    use super::*;
+
+    /// An app in `state` running only the system of its screen
+    fn menu_app(state: AppState) -> App {
+        let mut app = App::new();
+        app.add_plugin(InputPlugin)
+            .add_event::<AppExit>()
+            .init_resource::<GameConfig>()
+            .add_state(state)
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(main_menu_system))
+            .add_system_set(SystemSet::on_update(AppState::GameOver).with_system(game_over_system))
+            .add_system_set(SystemSet::on_update(AppState::Victory).with_system(game_over_system));
+        app
+    }
+
+    fn current(app: &App) -> AppState {
+        *app.world.resource::<State<AppState>>().current()
+    }
+
+    fn send<E: Send + Sync + 'static>(app: &mut App, event: E) {
+        app.world.resource_mut::<Events<E>>().send(event);
+    }
+
+    #[test]
+    fn taps_start_and_restart_the_game() {
+        for state in [AppState::MainMenu, AppState::GameOver, AppState::Victory] {
+            let mut app = menu_app(state);
+            app.update();
+            assert_eq!(current(&app), state);
+
+            send(
+                &mut app,
+                TouchInput {
+                    phase: TouchPhase::Started,
+                    position: Vec2::new(10.0, 20.0),
+                    force: None,
+                    id: 0,
+                },
+            );
+            app.update();
+            assert_eq!(current(&app), AppState::Playing, "{:?}", state);
+        }
+    }
+
+    #[test]
+    fn clicks_start_the_game() {
+        let mut app = menu_app(AppState::MainMenu);
+        send(
+            &mut app,
+            MouseButtonInput {
+                button: MouseButton::Right,
+                state: ButtonState::Pressed,
+            },
+        );
+        app.update();
+        assert_eq!(current(&app), AppState::MainMenu);
+
+        send(
+            &mut app,
+            MouseButtonInput {
+                button: MouseButton::Left,
+                state: ButtonState::Pressed,
+            },
+        );
+        app.update();
+        assert_eq!(current(&app), AppState::Playing);
+    }
+}
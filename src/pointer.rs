@@ -0,0 +1,230 @@
+//! Steering the first snake with the mouse or a touchscreen.
+//!
+//! A click or tap turns the snake toward the pointer, a swipe turns it in the
+//! direction of the swipe. Both go through the turn queue like keys do, so a
+//! snake never reverses into its neck. On the menus a tap starts the game,
+//! see [`tapped`](crate::menu::tapped).
+
+use bevy::{input::touch::Touch, prelude::*};
+
+use crate::{
+    config::GameConfig,
+    controls::TurnQueues,
+    game::{cell_to_translation, TickLabel},
+    sim::{Direction, GameState},
+    AppState,
+};
+
+/// Logical pixels a press has to move before it counts as a swipe
+const SWIPE_DISTANCE: f32 = 30.0;
+/// The snake steered with the pointer
+const POINTER_PLAYER: usize = 0;
+
+/// Plugin for mouse and touch steering
+pub struct PointerPlugin;
+
+impl Plugin for PointerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PointerGesture>().add_system_set(
+            SystemSet::on_update(AppState::Playing)
+                .label(TickLabel::Input)
+                .with_system(pointer_gesture_system)
+                .with_system(pointer_steering_system.after(pointer_gesture_system)),
+        );
+    }
+}
+
+/// A finished press of the mouse or a finger
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerGesture {
+    /// Pressed and released in about the same spot, in window coordinates
+    Tap(Vec2),
+    /// Moved by the given distance while pressed
+    Swipe(Vec2),
+}
+
+impl PointerGesture {
+    /// Tell a tap from a swipe by how far the pointer moved while pressed
+    pub fn new(start: Vec2, end: Vec2) -> Self {
+        if start.distance(end) >= SWIPE_DISTANCE {
+            PointerGesture::Swipe(end - start)
+        } else {
+            PointerGesture::Tap(end)
+        }
+    }
+}
+
+/// The direction to move along `delta` in, for a snake heading in
+/// `heading`. If that would reverse the snake it turns to the side `delta`
+/// leans to instead, if it leans to any side at all
+pub fn direction_along(delta: Vec2, heading: Option<Direction>) -> Option<Direction> {
+    let horizontal = match delta.x {
+        x if x > 0.0 => Some(Direction::Right),
+        x if x < 0.0 => Some(Direction::Left),
+        _ => None,
+    };
+    let vertical = match delta.y {
+        y if y > 0.0 => Some(Direction::Up),
+        y if y < 0.0 => Some(Direction::Down),
+        _ => None,
+    };
+    let (primary, secondary) = if delta.x.abs() > delta.y.abs() {
+        (horizontal, vertical)
+    } else {
+        (vertical, horizontal)
+    };
+
+    let reverse = heading.map(Direction::opposite);
+    [primary, secondary]
+        .into_iter()
+        .flatten()
+        .find(|&direction| Some(direction) != reverse)
+}
+
+/// Turn mouse clicks and touches into gestures once they are released.
+/// Presses that started on a menu, like the tap that started the game, are
+/// left out
+pub fn pointer_gesture_system(
+    mouse: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    windows: Res<Windows>,
+    mut press: Local<Option<Vec2>>,
+    mut touch_ids: Local<Vec<u64>>,
+    mut gestures: EventWriter<PointerGesture>,
+) {
+    let cursor = windows.get_primary().and_then(Window::cursor_position);
+    if mouse.just_pressed(MouseButton::Left) {
+        *press = cursor;
+    }
+    if mouse.just_released(MouseButton::Left) {
+        if let (Some(start), Some(end)) = (press.take(), cursor) {
+            gestures.send(PointerGesture::new(start, end));
+        }
+    }
+
+    touch_ids.retain(|&id| !touches.just_cancelled(id));
+    touch_ids.extend(touches.iter_just_pressed().map(Touch::id));
+    let height = windows.get_primary().map_or(0.0, Window::height);
+    for touch in touches.iter_just_released() {
+        let idx = match touch_ids.iter().position(|&id| id == touch.id()) {
+            Some(idx) => idx,
+            None => continue,
+        };
+        touch_ids.swap_remove(idx);
+        gestures.send(PointerGesture::new(
+            touch_to_window(touch.start_position(), height),
+            touch_to_window(touch.position(), height),
+        ));
+    }
+}
+
+/// Convert the position of a touch to window coordinates, which start in the
+/// bottom left corner like the cursor. Bevy does that itself only on mobile
+pub fn touch_to_window(pos: Vec2, window_height: f32) -> Vec2 {
+    if cfg!(any(target_os = "android", target_os = "ios")) {
+        pos
+    } else {
+        Vec2::new(pos.x, window_height - pos.y)
+    }
+}
+
+/// Queue the turns the gestures stand for
+pub fn pointer_steering_system(
+    mut gestures: EventReader<PointerGesture>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    state: Res<GameState>,
+    config: Res<GameConfig>,
+    mut queues: ResMut<TurnQueues>,
+) {
+    let (snake, queue) = match (
+        state.snakes().get(POINTER_PLAYER),
+        queues.0.get_mut(POINTER_PLAYER),
+    ) {
+        (Some(snake), Some(queue)) => (snake, queue),
+        _ => return,
+    };
+
+    for gesture in gestures.iter() {
+        let delta = match *gesture {
+            PointerGesture::Swipe(delta) => delta,
+            PointerGesture::Tap(pos) => {
+                let target = match cameras.get_single() {
+                    Ok((camera, transform)) => window_to_world(camera, transform, pos),
+                    Err(_) => continue,
+                };
+                let head = cell_to_translation(&config, snake.head());
+                target - head.truncate()
+            }
+        };
+
+        let heading = queue.planned(snake.direction());
+        if let Some(direction) = direction_along(delta, heading) {
+            queue.push(direction, snake.direction(), config.input_buffer);
+        }
+    }
+}
+
+/// Convert a position in the window to a position in the world as seen by
+/// the camera
+pub fn window_to_world(camera: &Camera, transform: &GlobalTransform, pos: Vec2) -> Vec2 {
+    let size = camera.logical_viewport_size().unwrap_or(Vec2::ONE);
+    let ndc = pos / size * 2.0 - Vec2::ONE;
+    let ndc_to_world = transform.compute_matrix() * camera.projection_matrix().inverse();
+    ndc_to_world.project_point3(ndc.extend(-1.0)).truncate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_presses_are_taps() {
+        let start = Vec2::new(100.0, 100.0);
+        let end = start + Vec2::new(SWIPE_DISTANCE - 1.0, 0.0);
+        assert_eq!(PointerGesture::new(start, end), PointerGesture::Tap(end));
+
+        let end = start + Vec2::new(0.0, -SWIPE_DISTANCE);
+        assert_eq!(
+            PointerGesture::new(start, end),
+            PointerGesture::Swipe(Vec2::new(0.0, -SWIPE_DISTANCE))
+        );
+    }
+
+    #[test]
+    fn direction_along_follows_the_longer_axis() {
+        let along = |x, y| direction_along(Vec2::new(x, y), Some(Direction::Up));
+        assert_eq!(along(10.0, 2.0), Some(Direction::Right));
+        assert_eq!(along(-10.0, -2.0), Some(Direction::Left));
+        assert_eq!(along(3.0, 10.0), Some(Direction::Up));
+        assert_eq!(along(0.0, 0.0), None);
+        assert_eq!(
+            direction_along(Vec2::new(0.0, -10.0), None),
+            Some(Direction::Down)
+        );
+    }
+
+    #[test]
+    fn direction_along_never_reverses() {
+        let heading = Some(Direction::Up);
+        assert_eq!(
+            direction_along(Vec2::new(-2.0, -10.0), heading),
+            Some(Direction::Left)
+        );
+        assert_eq!(direction_along(Vec2::new(0.0, -10.0), heading), None);
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    #[test]
+    fn touches_are_flipped_like_the_cursor() {
+        // Touches start at the top of the window, so this finger moved up
+        let start = touch_to_window(Vec2::new(200.0, 500.0), 720.0);
+        let end = touch_to_window(Vec2::new(200.0, 400.0), 720.0);
+        assert_eq!(start, Vec2::new(200.0, 220.0));
+        assert_eq!(
+            PointerGesture::new(start, end),
+            PointerGesture::Swipe(Vec2::new(0.0, 100.0))
+        );
+        let delta = end - start;
+        assert_eq!(direction_along(delta, None), Some(Direction::Up));
+    }
+}
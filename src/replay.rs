@@ -0,0 +1,528 @@
+//! Recording games and playing them back.
+//!
+//! A replay stores the rules, the tick rate, the seed and every direction
+//! change together with the tick it was applied at. As the simulation is
+//! deterministic that is enough to play the whole game again. Controls,
+//! looks and volumes are taken from the configuration of the player.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ConfigError, GameConfig},
+    events::GameEvents,
+    game::{
+        clear_board_system, spawn_game_entities, step_game, sync_snake_system, Score, TickLabel,
+    },
+    menu::UiFont,
+    sim::{Direction, GameState, Rules},
+    AppState,
+};
+
+/// Version of the file format written by this build. Bump it whenever the
+/// format changes, older replays are rejected
+pub const REPLAY_VERSION: u32 = 2;
+/// Slowest playback speed
+const MIN_SPEED: f64 = 1.0 / 8.0;
+/// Fastest playback speed
+const MAX_SPEED: f64 = 16.0;
+
+/// Plugin saving a replay of every game and playing replays back
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(save_replay_system))
+            .add_system_set(SystemSet::on_enter(AppState::Victory).with_system(save_replay_system))
+            .add_system_set(
+                SystemSet::on_enter(AppState::Replay)
+                    .with_system(clear_board_system)
+                    .with_system(start_playback_system)
+                    .with_system(spawn_playback_status_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Replay)
+                    .with_system(playback_control_system.label(TickLabel::Input))
+                    .with_system(
+                        playback_system
+                            .label(TickLabel::Step)
+                            .after(TickLabel::Input),
+                    )
+                    .with_system(
+                        sync_snake_system
+                            .label(TickLabel::Sync)
+                            .after(TickLabel::Step),
+                    )
+                    .with_system(playback_status_system.after(TickLabel::Step)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Replay).with_system(despawn_playback_status_system),
+            );
+    }
+}
+
+/// A recorded game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub rules: Rules,
+    /// Simulation ticks per second
+    pub tick_rate: f64,
+    pub seed: u64,
+    /// Number of ticks the game lasted
+    pub ticks: u64,
+    /// Every direction change, ordered by tick
+    pub inputs: Vec<ReplayInput>,
+}
+
+/// Only the version of a replay, read before the rest as the other fields
+/// differ between versions
+#[derive(Debug, Deserialize)]
+#[serde(rename = "Replay")]
+struct ReplayVersion {
+    version: u32,
+}
+
+/// A direction change of a snake. Saved as a `(tick, snake, direction)`
+/// tuple to keep replays small
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "(u64, u8, Direction)", into = "(u64, u8, Direction)")]
+pub struct ReplayInput {
+    /// The tick the direction was applied at
+    pub tick: u64,
+    pub snake: u8,
+    pub direction: Direction,
+}
+
+impl From<(u64, u8, Direction)> for ReplayInput {
+    fn from((tick, snake, direction): (u64, u8, Direction)) -> Self {
+        Self {
+            tick,
+            snake,
+            direction,
+        }
+    }
+}
+
+impl From<ReplayInput> for (u64, u8, Direction) {
+    fn from(input: ReplayInput) -> Self {
+        (input.tick, input.snake, input.direction)
+    }
+}
+
+/// Reasons a replay could not be used
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse(ron::Error),
+    /// Written by a newer or an older build of the game
+    UnsupportedVersion(u32),
+    /// The rules or the tick rate in the replay are out of range
+    Invalid(ConfigError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "io error: {}", err),
+            ReplayError::Parse(err) => write!(f, "invalid replay: {}", err),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "unsupported replay version {}", version)
+            }
+            ReplayError::Invalid(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+impl From<ron::Error> for ReplayError {
+    fn from(err: ron::Error) -> Self {
+        ReplayError::Parse(err)
+    }
+}
+
+impl Replay {
+    /// `replays` in the data directory of the user
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("bevy_snake").join("replays"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let content = fs::read_to_string(path)?;
+        let ReplayVersion { version } = ron::from_str(&content)?;
+        if version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let replay: Self = ron::from_str(&content)?;
+        replay
+            .config(&GameConfig::default())
+            .validate()
+            .map_err(ReplayError::Invalid)?;
+        Ok(replay)
+    }
+
+    /// `config` with the rules and the tick rate of the recorded game
+    pub fn config(&self, config: &GameConfig) -> GameConfig {
+        GameConfig {
+            board_width: self.rules.board.width,
+            board_height: self.rules.board.height,
+            mode: self.rules.mode,
+            players: self.rules.players,
+            tick_rate: self.tick_rate,
+            seed: Some(self.seed),
+            ..config.clone()
+        }
+    }
+
+    /// Write the replay to a file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, ron::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The inputs of every snake at the given tick
+    pub fn inputs_at(&self, tick: u64, players: usize) -> Vec<Option<Direction>> {
+        let start = self.inputs.partition_point(|input| input.tick < tick);
+        let mut inputs = vec![None; players];
+        for input in self.inputs[start..]
+            .iter()
+            .take_while(|input| input.tick == tick)
+        {
+            if let Some(slot) = inputs.get_mut(usize::from(input.snake)) {
+                *slot = Some(input.direction);
+            }
+        }
+        inputs
+    }
+}
+
+/// The replay of the game currently played
+#[derive(Debug)]
+pub struct ReplayRecorder(pub Replay);
+
+impl Default for ReplayRecorder {
+    fn default() -> Self {
+        Self::new(&GameConfig::default(), 0)
+    }
+}
+
+impl ReplayRecorder {
+    /// Start recording a game
+    pub fn new(config: &GameConfig, seed: u64) -> Self {
+        Self(Replay {
+            version: REPLAY_VERSION,
+            rules: config.rules(),
+            tick_rate: config.tick_rate,
+            seed,
+            ticks: 0,
+            inputs: Vec::new(),
+        })
+    }
+
+    /// Record the inputs passed to the simulation at the given tick
+    pub fn record(&mut self, tick: u64, inputs: &[Option<Direction>]) {
+        for (snake, direction) in inputs.iter().enumerate() {
+            if let Some(direction) = *direction {
+                self.0.inputs.push(ReplayInput {
+                    tick,
+                    snake: snake as u8,
+                    direction,
+                });
+            }
+        }
+        self.0.ticks = tick + 1;
+    }
+}
+
+/// A replay being played back
+#[derive(Debug)]
+pub struct Playback {
+    pub replay: Replay,
+    /// Multiplier for the tick rate
+    pub speed: f64,
+    pub paused: bool,
+    /// Play a single tick even though paused
+    step_once: bool,
+    /// Seconds of playback time not yet turned into ticks
+    accumulator: f64,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            speed: 1.0,
+            paused: false,
+            step_once: false,
+            accumulator: 0.0,
+        }
+    }
+
+    /// How far the playback is into the next tick, from 0 to 1
+    pub fn progress(&self, state: &GameState, config: &GameConfig) -> f32 {
+        if self.is_finished(state) {
+            1.0
+        } else {
+            (self.accumulator / config.tick_step()) as f32
+        }
+    }
+
+    /// Whether the recorded game has been played to its end
+    pub fn is_finished(&self, state: &GameState) -> bool {
+        state.is_over() || state.tick() >= self.replay.ticks
+    }
+}
+
+/// Save the replay of the game that just ended
+pub fn save_replay_system(recorder: Res<ReplayRecorder>) {
+    let dir = match Replay::default_dir() {
+        Some(dir) => dir,
+        None => return,
+    };
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let path = dir.join(format!("{}-{}.ron", secs, recorder.0.seed));
+
+    match recorder.0.save(&path) {
+        Ok(()) => info!("Saved replay to {}", path.display()),
+        Err(err) => warn!("Could not save replay to {}: {}", path.display(), err),
+    }
+}
+
+/// Set up the recorded game from its start
+pub fn start_playback_system(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut playback: ResMut<Playback>,
+    mut state: ResMut<GameState>,
+    mut score: ResMut<Score>,
+) {
+    *state = GameState::new(playback.replay.rules.clone(), playback.replay.seed);
+    *score = Score(vec![0; state.snakes().len()]);
+    playback.accumulator = 0.0;
+    playback.step_once = false;
+
+    spawn_game_entities(&mut commands, &config, &state);
+}
+
+/// Pause, change the speed, step through or leave the replay
+pub fn playback_control_system(
+    mut input: ResMut<Input<KeyCode>>,
+    mut playback: ResMut<Playback>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    if input.clear_just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+    }
+    if input.clear_just_pressed(KeyCode::Up) || input.clear_just_pressed(KeyCode::Equals) {
+        playback.speed = (playback.speed * 2.0).min(MAX_SPEED);
+    }
+    if input.clear_just_pressed(KeyCode::Down) || input.clear_just_pressed(KeyCode::Minus) {
+        playback.speed = (playback.speed / 2.0).max(MIN_SPEED);
+    }
+    if input.clear_just_pressed(KeyCode::Right) || input.clear_just_pressed(KeyCode::Period) {
+        playback.paused = true;
+        playback.step_once = true;
+    }
+    if input.clear_just_pressed(KeyCode::R) {
+        let _ = app_state.restart();
+    } else if input.clear_just_pressed(KeyCode::Escape) {
+        let _ = app_state.replace(AppState::MainMenu);
+    }
+}
+
+/// Feed the recorded inputs into the simulation at the playback speed
+pub fn playback_system(
+    time: Res<Time>,
+    config: Res<GameConfig>,
+    mut playback: ResMut<Playback>,
+    mut state: ResMut<GameState>,
+    mut score: ResMut<Score>,
+    mut events: GameEvents,
+) {
+    let mut steps = u32::from(std::mem::take(&mut playback.step_once));
+    if !playback.paused {
+        playback.accumulator += time.delta_seconds_f64() * playback.speed;
+        while playback.accumulator >= config.tick_step() {
+            playback.accumulator -= config.tick_step();
+            steps += 1;
+        }
+    }
+
+    for _ in 0..steps {
+        if playback.is_finished(&state) {
+            playback.accumulator = 0.0;
+            break;
+        }
+        let inputs = playback
+            .replay
+            .inputs_at(state.tick(), state.snakes().len());
+        events.send(&step_game(&mut state, &mut score, &inputs));
+    }
+}
+
+/// Text showing the state of the playback
+#[derive(Component, Debug)]
+pub struct PlaybackStatus;
+
+pub fn spawn_playback_status_system(
+    mut commands: Commands,
+    font: Res<UiFont>,
+    statuses: Query<Entity, With<PlaybackStatus>>,
+) {
+    for status in statuses.iter() {
+        commands.entity(status).despawn_recursive();
+    }
+
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font.0.clone(),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+        )
+        .insert(PlaybackStatus);
+}
+
+pub fn despawn_playback_status_system(
+    mut commands: Commands,
+    statuses: Query<Entity, With<PlaybackStatus>>,
+) {
+    for status in statuses.iter() {
+        commands.entity(status).despawn_recursive();
+    }
+}
+
+/// Show speed, progress and controls of the playback
+pub fn playback_status_system(
+    playback: Res<Playback>,
+    state: Res<GameState>,
+    mut statuses: Query<&mut Text, With<PlaybackStatus>>,
+) {
+    for mut text in statuses.iter_mut() {
+        let status = if playback.is_finished(&state) {
+            "finished"
+        } else if playback.paused {
+            "paused"
+        } else {
+            "playing"
+        };
+        text.sections[0].value = format!(
+            "Replay {}  x{}  tick {}/{}\n\
+             Space: pause  Up/Down: speed  Right: step  R: restart  Escape: menu",
+            status,
+            playback.speed,
+            state.tick(),
+            playback.replay.ticks,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Save the replay of a game with the given config and load it again
+    fn save_and_load(name: &str, config: GameConfig) -> Result<Replay, ReplayError> {
+        let path =
+            std::env::temp_dir().join(format!("bevy_snake_{}_{}.ron", name, std::process::id()));
+        ReplayRecorder::new(&config, 3).0.save(&path).unwrap();
+        let replay = Replay::load(&path);
+        fs::remove_file(path).unwrap();
+        replay
+    }
+
+    #[test]
+    fn load_accepts_valid_replays() {
+        let config = GameConfig {
+            tick_rate: 12.0,
+            ..Default::default()
+        };
+        let replay = save_and_load("valid_replay", config.clone()).unwrap();
+        assert_eq!(replay.rules, config.rules());
+        assert_eq!(replay.tick_rate, 12.0);
+        assert_eq!(replay.seed, 3);
+    }
+
+    #[test]
+    fn replays_keep_the_config_of_the_player() {
+        let recorded = GameConfig {
+            board_width: 30,
+            players: 2,
+            tick_rate: 8.0,
+            ..Default::default()
+        };
+        let replay = ReplayRecorder::new(&recorded, 3).0;
+        let player = GameConfig {
+            fullscreen: true,
+            cell_size: 20.0,
+            ..Default::default()
+        };
+        let config = replay.config(&player);
+        assert_eq!(config.rules(), recorded.rules());
+        assert_eq!(config.tick_rate, 8.0);
+        assert_eq!(config.seed, Some(3));
+        assert!(config.fullscreen);
+        assert_eq!(config.cell_size, 20.0);
+        assert_eq!(config.bindings, player.bindings);
+    }
+
+    #[test]
+    fn load_rejects_other_versions() {
+        let path =
+            std::env::temp_dir().join(format!("bevy_snake_old_replay_{}.ron", std::process::id()));
+        let old = "(version:1,config:(colors:(snake:\"00ff00\")),seed:3,ticks:0,inputs:[])";
+        fs::write(&path, old).unwrap();
+        let err = Replay::load(&path).unwrap_err();
+        fs::remove_file(path).unwrap();
+        assert!(matches!(err, ReplayError::UnsupportedVersion(1)));
+    }
+
+    #[test]
+    fn load_rejects_configs_out_of_range() {
+        for config in [
+            GameConfig {
+                tick_rate: -5.0,
+                ..Default::default()
+            },
+            GameConfig {
+                board_width: -20,
+                ..Default::default()
+            },
+        ] {
+            let err = save_and_load("invalid_replay", config).unwrap_err();
+            assert!(matches!(err, ReplayError::Invalid(ConfigError::Invalid(_))));
+        }
+    }
+}
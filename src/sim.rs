@@ -0,0 +1,1126 @@
+//! Headless snake simulation.
+//!
+//! Holds the board, the snakes and the fruit and advances them one tick at a
+//! time. Nothing in here touches bevy rendering, so it can be driven by tests,
+//! bots and tools just as well as by the game itself.
+
+use std::{collections::VecDeque, fmt, str::FromStr};
+
+use bevy::math::IVec2;
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+use serde::{Deserialize, Serialize};
+
+/// Random number generator of a game. Unlike `SmallRng` its output is the same
+/// on every platform, so a seed gives the same game everywhere
+pub type GameRng = Xoshiro256PlusPlus;
+
+/// A direction a snake can move in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// The offset of a single step in this direction
+    pub fn offset(self) -> IVec2 {
+        match self {
+            Direction::Up => IVec2::new(0, 1),
+            Direction::Down => IVec2::new(0, -1),
+            Direction::Left => IVec2::new(-1, 0),
+            Direction::Right => IVec2::new(1, 0),
+        }
+    }
+
+    /// The direction pointing the other way
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    /// The direction after turning left, counterclockwise
+    pub fn turned_left(self) -> Self {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Up,
+        }
+    }
+
+    /// The direction after turning right, clockwise
+    pub fn turned_right(self) -> Self {
+        self.turned_left().opposite()
+    }
+}
+
+/// The playing field. Cells go from `(0, 0)` to `(width - 1, height - 1)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Board {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Board {
+    /// Create a board with the given size in cells
+    pub fn new(width: i32, height: i32) -> Self {
+        Self { width, height }
+    }
+
+    /// Check if the cell lies on the board
+    pub fn contains(&self, pos: IVec2) -> bool {
+        (0..self.width).contains(&pos.x) && (0..self.height).contains(&pos.y)
+    }
+
+    /// The cell in the middle of the board
+    pub fn center(&self) -> IVec2 {
+        IVec2::new(self.width / 2, self.height / 2)
+    }
+
+    /// Index of the cell in row major order, `None` if it is not on the board
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        self.contains(pos)
+            .then(|| (pos.y * self.width + pos.x) as usize)
+    }
+}
+
+/// Marks a cell in [`FreeCells::slots`] as taken
+const OCCUPIED: u32 = u32::MAX;
+
+/// The cells of a board no snake is on.
+///
+/// Free cells are kept in a list, with the position of every cell in that
+/// list stored next to it. Taking a cell swaps it with the last entry, so
+/// occupying, releasing and picking a random cell all take constant time no
+/// matter how big the board or the snakes are.
+#[derive(Debug, Clone)]
+pub struct FreeCells {
+    board: Board,
+    /// Every free cell, in no particular order
+    cells: Vec<IVec2>,
+    /// Position of each cell of the board in `cells`, by row major index
+    slots: Vec<u32>,
+}
+
+impl FreeCells {
+    /// Every cell of the board free
+    pub fn new(board: Board) -> Self {
+        let cells = (0..board.height)
+            .flat_map(|y| (0..board.width).map(move |x| IVec2::new(x, y)))
+            .collect::<Vec<_>>();
+        let slots = (0..cells.len() as u32).collect();
+        Self {
+            board,
+            cells,
+            slots,
+        }
+    }
+
+    /// Number of free cells
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Whether the cell is on the board and not taken
+    pub fn is_free(&self, pos: IVec2) -> bool {
+        self.board
+            .index(pos)
+            .is_some_and(|idx| self.slots[idx] != OCCUPIED)
+    }
+
+    /// Take the cell. Cells that are taken already or off the board are
+    /// ignored
+    pub fn occupy(&mut self, pos: IVec2) {
+        let idx = match self.board.index(pos) {
+            Some(idx) if self.slots[idx] != OCCUPIED => idx,
+            _ => return,
+        };
+
+        let slot = self.slots[idx] as usize;
+        self.cells.swap_remove(slot);
+        if let Some(&moved) = self.cells.get(slot) {
+            if let Some(moved) = self.board.index(moved) {
+                self.slots[moved] = slot as u32;
+            }
+        }
+        self.slots[idx] = OCCUPIED;
+    }
+
+    /// Free the cell again. Cells that are free already or off the board are
+    /// ignored
+    pub fn release(&mut self, pos: IVec2) {
+        let idx = match self.board.index(pos) {
+            Some(idx) if self.slots[idx] == OCCUPIED => idx,
+            _ => return,
+        };
+
+        self.slots[idx] = self.cells.len() as u32;
+        self.cells.push(pos);
+    }
+
+    /// Pick a free cell uniformly at random, `None` if the board is full
+    pub fn random<R: Rng>(&self, rng: &mut R) -> Option<IVec2> {
+        if self.cells.is_empty() {
+            return None;
+        }
+        Some(self.cells[rng.gen_range(0..self.cells.len())])
+    }
+}
+
+/// The rule set a game is played with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    /// Leaving the board kills the snake
+    #[default]
+    Classic,
+    /// The snake reappears on the opposite edge of the board
+    Wrap,
+    /// The left and right edges wrap around, top and bottom are walls
+    WrapHorizontal,
+    /// The top and bottom edges wrap around, left and right are walls
+    WrapVertical,
+}
+
+impl GameMode {
+    /// Every mode there is
+    pub const ALL: &'static [GameMode] = &[
+        GameMode::Classic,
+        GameMode::Wrap,
+        GameMode::WrapHorizontal,
+        GameMode::WrapVertical,
+    ];
+
+    /// The name used in config files and on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Classic => "classic",
+            GameMode::Wrap => "wrap",
+            GameMode::WrapHorizontal => "wrap_horizontal",
+            GameMode::WrapVertical => "wrap_vertical",
+        }
+    }
+
+    /// Whether leaving the board to the left or right wraps around
+    pub fn wraps_horizontally(self) -> bool {
+        matches!(self, GameMode::Wrap | GameMode::WrapHorizontal)
+    }
+
+    /// Whether leaving the board to the top or bottom wraps around
+    pub fn wraps_vertically(self) -> bool {
+        matches!(self, GameMode::Wrap | GameMode::WrapVertical)
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GameMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| {
+                let names = GameMode::ALL.iter().map(|mode| mode.name());
+                format!(
+                    "unknown game mode `{}`, expected one of {}",
+                    s,
+                    names.collect::<Vec<_>>().join(", ")
+                )
+            })
+    }
+}
+
+/// Everything a game is set up with, apart from the seed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rules {
+    pub board: Board,
+    pub mode: GameMode,
+    /// Number of snakes
+    pub players: usize,
+}
+
+impl Rules {
+    /// The cell a single step in `direction` from `pos` leads to. Across a
+    /// wrapping edge that is a cell on the opposite edge, across a wall it is
+    /// a cell off the board
+    pub fn neighbour(&self, pos: IVec2, direction: Direction) -> IVec2 {
+        let mut next = pos + direction.offset();
+        if self.mode.wraps_horizontally() {
+            next.x = next.x.rem_euclid(self.board.width);
+        }
+        if self.mode.wraps_vertically() {
+            next.y = next.y.rem_euclid(self.board.height);
+        }
+        next
+    }
+}
+
+/// Why a snake died
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeathCause {
+    /// The snake left the board
+    Wall,
+    /// The snake ran into itself
+    OwnBody,
+    /// The snake ran into the body of another snake
+    OtherSnake { snake: usize },
+    /// The snake met the head of another snake, both died
+    HeadOn { snake: usize },
+}
+
+/// Something that happened during a single step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameEvent {
+    /// A snake changed its direction, or got its first one
+    SnakeTurned { snake: usize, direction: Direction },
+    /// A snake ate the fruit at the given position
+    FruitEaten { snake: usize, pos: IVec2 },
+    /// A snake got longer, the tick after it ate
+    SnakeGrew { snake: usize, length: usize },
+    /// A new fruit was placed
+    FruitSpawned { pos: IVec2 },
+    /// A snake died
+    SnakeDied { snake: usize, cause: DeathCause },
+    /// There was no free cell left for a new fruit, the snakes filled the
+    /// whole board
+    Won,
+    /// The game ended, either because no snake is alive anymore or because
+    /// it was won
+    GameOver,
+}
+
+/// A single snake
+#[derive(Debug, Clone)]
+pub struct Snake {
+    /// Occupied cells, head first
+    body: VecDeque<IVec2>,
+    /// `None` until the snake got its first direction
+    direction: Option<Direction>,
+    /// Segments that still have to be added
+    growth: u32,
+    /// Cells the head moved so far
+    moves: u64,
+    alive: bool,
+}
+
+impl Snake {
+    /// Create a snake consisting only of its head
+    pub fn new(head: IVec2) -> Self {
+        Self {
+            body: VecDeque::from([head]),
+            direction: None,
+            growth: 0,
+            moves: 0,
+            alive: true,
+        }
+    }
+
+    /// The cell of the head
+    pub fn head(&self) -> IVec2 {
+        self.body[0]
+    }
+
+    /// All occupied cells, head first
+    pub fn body(&self) -> impl ExactSizeIterator<Item = IVec2> + '_ {
+        self.body.iter().copied()
+    }
+
+    /// Number of occupied cells
+    pub fn length(&self) -> usize {
+        self.body.len()
+    }
+
+    /// The direction the snake is moving in
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    /// Number of cells the head moved since the game started
+    pub fn moves(&self) -> u64 {
+        self.moves
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.alive
+    }
+}
+
+/// The complete state of a game.
+///
+/// Every random choice is made with the rng of the game, so the same seed and
+/// the same inputs always lead to the same game.
+#[derive(Debug, Clone)]
+pub struct GameState {
+    rules: Rules,
+    snakes: Vec<Snake>,
+    /// `None` once the board is full
+    fruit: Option<IVec2>,
+    /// Cells not taken by any snake, where fruits can spawn
+    free: FreeCells,
+    rng: GameRng,
+    /// The seed `rng` was created from
+    seed: u64,
+    /// Number of steps taken so far
+    tick: u64,
+    over: bool,
+    won: bool,
+}
+
+impl GameState {
+    /// Start a new game with the snakes spread along the middle row of the
+    /// board. Every random choice is derived from `seed`.
+    pub fn new(rules: Rules, seed: u64) -> Self {
+        let board = rules.board;
+        let mut rng = GameRng::seed_from_u64(seed);
+        let players = rules.players.max(1) as i32;
+        let snakes = (1..=players)
+            .map(|idx| {
+                let x = board.width * idx / (players + 1);
+                Snake::new(IVec2::new(x, board.center().y))
+            })
+            .collect::<Vec<_>>();
+
+        let mut free = FreeCells::new(board);
+        for snake in &snakes {
+            free.occupy(snake.head());
+        }
+        let fruit = free.random(&mut rng);
+
+        Self {
+            rules,
+            snakes,
+            fruit,
+            free,
+            rng,
+            seed,
+            tick: 0,
+            over: false,
+            won: false,
+        }
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn board(&self) -> Board {
+        self.rules.board
+    }
+
+    pub fn snakes(&self) -> &[Snake] {
+        &self.snakes
+    }
+
+    /// The cell of the fruit, `None` once the snakes filled the board
+    pub fn fruit(&self) -> Option<IVec2> {
+        self.fruit
+    }
+
+    /// Cells no snake is on
+    pub fn free_cells(&self) -> &FreeCells {
+        &self.free
+    }
+
+    /// The seed the game was started with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Number of steps taken so far
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Whether the game ended, won or not
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    /// Whether the snakes filled the whole board
+    pub fn is_won(&self) -> bool {
+        self.won
+    }
+
+    /// Advance the game by one tick.
+    ///
+    /// `inputs` holds the new direction for each snake by index, missing or
+    /// `None` entries keep the current direction. Turns opposite to the
+    /// current direction are ignored.
+    ///
+    /// A tick always runs the same phases in the same order: turn, move,
+    /// collide, grow and spawn. Every snake sees the board as it was at the
+    /// start of a phase, so the order of the snakes does not matter. When no
+    /// free cell is left for the next fruit the game is won.
+    pub fn step(&mut self, inputs: &[Option<Direction>]) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        if self.over {
+            return events;
+        }
+        self.tick += 1;
+
+        self.turn(inputs, &mut events);
+        let moves = self.planned_moves();
+        self.collide(&moves, &mut events);
+        self.advance(&moves, &mut events);
+        let eaten = self.grow(&mut events);
+        if eaten {
+            self.spawn_fruit(&mut events);
+        }
+
+        if self.won || self.snakes.iter().all(|snake| !snake.alive) {
+            self.over = true;
+            events.push(GameEvent::GameOver);
+        }
+
+        events
+    }
+
+    /// Apply the inputs to the directions of the snakes
+    fn turn(&mut self, inputs: &[Option<Direction>], events: &mut Vec<GameEvent>) {
+        for (idx, (snake, input)) in self.snakes.iter_mut().zip(inputs).enumerate() {
+            // Turning back would run the head straight into the neck
+            if let Some(direction) = *input {
+                let turns = snake.direction != Some(direction)
+                    && snake.direction != Some(direction.opposite());
+                if snake.alive && turns {
+                    snake.direction = Some(direction);
+                    events.push(GameEvent::SnakeTurned {
+                        snake: idx,
+                        direction,
+                    });
+                }
+            }
+        }
+    }
+
+    /// The cell every snake moves its head to, `None` for snakes that stay
+    fn planned_moves(&self) -> Vec<Option<IVec2>> {
+        self.snakes
+            .iter()
+            .map(|snake| {
+                let direction = snake.direction.filter(|_| snake.alive)?;
+                Some(self.rules.neighbour(snake.head(), direction))
+            })
+            .collect()
+    }
+
+    /// Kill the snakes whose move ends in a wall, a body or another head
+    fn collide(&mut self, moves: &[Option<IVec2>], events: &mut Vec<GameEvent>) {
+        // A dying snake does not move, so its tail stays in the way of the
+        // others and one death can cause another. Repeat until none is added
+        let mut causes = vec![None; self.snakes.len()];
+        loop {
+            let dying = causes.iter().map(Option::is_some).collect::<Vec<_>>();
+            let mut changed = false;
+            for (idx, cause) in causes.iter_mut().enumerate() {
+                if cause.is_none() {
+                    *cause = self.collision(idx, moves, &dying);
+                    changed |= cause.is_some();
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        for (idx, cause) in causes.into_iter().enumerate() {
+            if let Some(cause) = cause {
+                self.snakes[idx].alive = false;
+                events.push(GameEvent::SnakeDied { snake: idx, cause });
+            }
+        }
+    }
+
+    /// What the planned move of a snake runs into, if anything.
+    ///
+    /// Two heads moving into the same cell or through each other kill both
+    /// snakes. Tails move away in the same tick, so they are no obstacle
+    /// unless their snake is growing, does not move or is `dying`.
+    fn collision(&self, idx: usize, moves: &[Option<IVec2>], dying: &[bool]) -> Option<DeathCause> {
+        let new_head = moves[idx]?;
+        if !self.rules.board.contains(new_head) {
+            return Some(DeathCause::Wall);
+        }
+
+        let head = self.snakes[idx].head();
+        for (other, other_move) in moves.iter().enumerate() {
+            let other_move = match *other_move {
+                Some(other_move) if other != idx => other_move,
+                _ => continue,
+            };
+            let swapped = other_move == head && self.snakes[other].head() == new_head;
+            if other_move == new_head || swapped {
+                return Some(DeathCause::HeadOn { snake: other });
+            }
+        }
+
+        for (other, snake) in self.snakes.iter().enumerate() {
+            let tail_leaves = moves[other].is_some() && !dying[other] && snake.growth == 0;
+            let obstacles = snake.body.len() - usize::from(tail_leaves);
+            if snake
+                .body
+                .iter()
+                .take(obstacles)
+                .any(|&part| part == new_head)
+            {
+                return Some(if other == idx {
+                    DeathCause::OwnBody
+                } else {
+                    DeathCause::OtherSnake { snake: other }
+                });
+            }
+        }
+        None
+    }
+
+    /// Move the surviving snakes, leaving the tail in place while growing
+    fn advance(&mut self, moves: &[Option<IVec2>], events: &mut Vec<GameEvent>) {
+        let mut heads = Vec::with_capacity(moves.len());
+        for (idx, (snake, new_head)) in self.snakes.iter_mut().zip(moves).enumerate() {
+            let new_head = match new_head {
+                Some(new_head) if snake.alive => *new_head,
+                _ => continue,
+            };
+
+            let grows = snake.growth > 0;
+            if grows {
+                snake.growth -= 1;
+            } else if let Some(tail) = snake.body.pop_back() {
+                self.free.release(tail);
+            }
+            snake.body.push_front(new_head);
+            if grows {
+                events.push(GameEvent::SnakeGrew {
+                    snake: idx,
+                    length: snake.body.len(),
+                });
+            }
+            snake.moves += 1;
+            heads.push(new_head);
+        }
+
+        // A head may move into a cell another snake's tail just left, so
+        // only take the new cells once every tail is gone
+        for head in heads {
+            self.free.occupy(head);
+        }
+    }
+
+    /// Let a snake whose head is on the fruit eat it. Returns whether the
+    /// fruit was eaten
+    fn grow(&mut self, events: &mut Vec<GameEvent>) -> bool {
+        let fruit = match self.fruit {
+            Some(fruit) => fruit,
+            None => return false,
+        };
+        let eater = self
+            .snakes
+            .iter()
+            .position(|snake| snake.alive && snake.head() == fruit);
+
+        match eater {
+            Some(idx) => {
+                self.snakes[idx].growth += 1;
+                events.push(GameEvent::FruitEaten {
+                    snake: idx,
+                    pos: fruit,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Place a new fruit on a free cell, or win the game if there is none
+    fn spawn_fruit(&mut self, events: &mut Vec<GameEvent>) {
+        self.fruit = self.free.random(&mut self.rng);
+        match self.fruit {
+            Some(pos) => events.push(GameEvent::FruitSpawned { pos }),
+            None => {
+                self.won = true;
+                events.push(GameEvent::Won);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: i32, y: i32) -> IVec2 {
+        IVec2::new(x, y)
+    }
+
+    /// A snake made of `body`, head first, that moved in `direction` last
+    fn snake(body: &[(i32, i32)], direction: Direction) -> Snake {
+        let mut snake = Snake::new(pos(body[0].0, body[0].1));
+        snake.body = body.iter().map(|&(x, y)| pos(x, y)).collect();
+        snake.direction = Some(direction);
+        snake
+    }
+
+    /// A game with the snakes and the fruit placed by hand
+    fn game(board: Board, mode: GameMode, snakes: Vec<Snake>, fruit: Option<IVec2>) -> GameState {
+        let rules = Rules {
+            board,
+            mode,
+            players: snakes.len(),
+        };
+        let mut state = GameState::new(rules, 0);
+        state.free = FreeCells::new(board);
+        for part in snakes.iter().flat_map(|snake| snake.body()) {
+            state.free.occupy(part);
+        }
+        state.snakes = snakes;
+        state.fruit = fruit;
+        state
+    }
+
+    fn died(snake: usize, cause: DeathCause) -> GameEvent {
+        GameEvent::SnakeDied { snake, cause }
+    }
+
+    /// Check that every free cell knows its slot and nothing else is free
+    fn assert_consistent(free: &FreeCells) {
+        for (slot, &cell) in free.cells.iter().enumerate() {
+            assert_eq!(free.slots[free.board.index(cell).unwrap()], slot as u32);
+        }
+        let free_slots = free.slots.iter().filter(|&&slot| slot != OCCUPIED);
+        assert_eq!(free_slots.count(), free.len());
+    }
+
+    #[test]
+    fn leaving_the_board_hits_the_wall() {
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::Classic,
+            vec![snake(&[(2, 4)], Direction::Up)],
+            Some(pos(0, 0)),
+        );
+        let events = state.step(&[]);
+        assert_eq!(events, [died(0, DeathCause::Wall), GameEvent::GameOver]);
+        assert_eq!(state.snakes()[0].head(), pos(2, 4));
+        assert!(state.is_over());
+    }
+
+    #[test]
+    fn running_into_the_own_body_kills() {
+        let body = [(1, 1), (2, 1), (2, 2), (1, 2), (0, 2)];
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::Classic,
+            vec![snake(&body, Direction::Left)],
+            Some(pos(4, 4)),
+        );
+        let events = state.step(&[Some(Direction::Up)]);
+        assert_eq!(
+            events[1..],
+            [died(0, DeathCause::OwnBody), GameEvent::GameOver]
+        );
+    }
+
+    #[test]
+    fn running_into_another_snake_kills() {
+        let other = [(1, 2), (1, 1), (1, 0), (2, 0)];
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::Classic,
+            vec![
+                snake(&[(0, 1)], Direction::Right),
+                snake(&other, Direction::Up),
+            ],
+            Some(pos(4, 4)),
+        );
+        let events = state.step(&[]);
+        assert_eq!(events, [died(0, DeathCause::OtherSnake { snake: 1 })]);
+        assert!(!state.snakes()[0].is_alive());
+        assert_eq!(state.snakes()[1].head(), pos(1, 3));
+    }
+
+    #[test]
+    fn heads_meeting_kill_both_snakes() {
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::Classic,
+            vec![
+                snake(&[(1, 2)], Direction::Right),
+                snake(&[(3, 2)], Direction::Left),
+            ],
+            Some(pos(0, 0)),
+        );
+        let events = state.step(&[]);
+        assert_eq!(
+            events,
+            [
+                died(0, DeathCause::HeadOn { snake: 1 }),
+                died(1, DeathCause::HeadOn { snake: 0 }),
+                GameEvent::GameOver,
+            ]
+        );
+    }
+
+    #[test]
+    fn heads_swapping_cells_kill_both_snakes() {
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::Classic,
+            vec![
+                snake(&[(1, 2)], Direction::Right),
+                snake(&[(2, 2)], Direction::Left),
+            ],
+            Some(pos(0, 0)),
+        );
+        let events = state.step(&[]);
+        assert_eq!(
+            events,
+            [
+                died(0, DeathCause::HeadOn { snake: 1 }),
+                died(1, DeathCause::HeadOn { snake: 0 }),
+                GameEvent::GameOver,
+            ]
+        );
+    }
+
+    #[test]
+    fn the_tail_leaving_frees_its_cell() {
+        let body = [(1, 1), (2, 1), (2, 2), (1, 2)];
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::Classic,
+            vec![snake(&body, Direction::Left)],
+            Some(pos(4, 4)),
+        );
+        let events = state.step(&[Some(Direction::Up)]);
+        assert_eq!(
+            events,
+            [GameEvent::SnakeTurned {
+                snake: 0,
+                direction: Direction::Up
+            }]
+        );
+        let body = state.snakes()[0].body().collect::<Vec<_>>();
+        assert_eq!(body, [pos(1, 2), pos(1, 1), pos(2, 1), pos(2, 2)]);
+        assert!(!state.free_cells().is_free(pos(1, 2)));
+        assert_consistent(state.free_cells());
+    }
+
+    #[test]
+    fn the_tail_stays_while_growing() {
+        let body = [(1, 1), (2, 1), (2, 2), (1, 2)];
+        let mut growing = snake(&body, Direction::Left);
+        growing.growth = 1;
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::Classic,
+            vec![growing],
+            Some(pos(4, 4)),
+        );
+        let events = state.step(&[Some(Direction::Up)]);
+        assert_eq!(
+            events[1..],
+            [died(0, DeathCause::OwnBody), GameEvent::GameOver]
+        );
+    }
+
+    #[test]
+    fn the_tail_of_a_dying_snake_stays() {
+        let mut state = game(
+            Board::new(5, 6),
+            GameMode::Classic,
+            vec![
+                snake(&[(2, 5), (2, 4)], Direction::Up),
+                snake(&[(1, 4)], Direction::Right),
+            ],
+            Some(pos(4, 0)),
+        );
+        let events = state.step(&[]);
+        assert_eq!(
+            events,
+            [
+                died(0, DeathCause::Wall),
+                died(1, DeathCause::OtherSnake { snake: 0 }),
+                GameEvent::GameOver,
+            ]
+        );
+        assert_eq!(
+            state.snakes()[0].body().collect::<Vec<_>>(),
+            [pos(2, 5), pos(2, 4)]
+        );
+        assert_eq!(state.snakes()[1].body().collect::<Vec<_>>(), [pos(1, 4)]);
+        assert!(!state.free_cells().is_free(pos(2, 4)));
+        assert_consistent(state.free_cells());
+    }
+
+    #[test]
+    fn deaths_keep_further_tails_in_place() {
+        let mut state = game(
+            Board::new(5, 6),
+            GameMode::Classic,
+            vec![
+                snake(&[(2, 5), (2, 4)], Direction::Up),
+                snake(&[(1, 4), (1, 3)], Direction::Right),
+                snake(&[(0, 3)], Direction::Right),
+                snake(&[(4, 0)], Direction::Left),
+            ],
+            Some(pos(4, 5)),
+        );
+        let events = state.step(&[]);
+        assert_eq!(
+            events,
+            [
+                died(0, DeathCause::Wall),
+                died(1, DeathCause::OtherSnake { snake: 0 }),
+                died(2, DeathCause::OtherSnake { snake: 1 }),
+            ]
+        );
+        assert!(!state.free_cells().is_free(pos(1, 3)));
+        assert_consistent(state.free_cells());
+    }
+
+    #[test]
+    fn snakes_grow_the_tick_after_eating() {
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::Classic,
+            vec![snake(&[(1, 2)], Direction::Right)],
+            Some(pos(2, 2)),
+        );
+
+        let events = state.step(&[]);
+        assert_eq!(
+            events[0],
+            GameEvent::FruitEaten {
+                snake: 0,
+                pos: pos(2, 2)
+            }
+        );
+        assert!(matches!(events[1], GameEvent::FruitSpawned { .. }));
+        assert_eq!(events.len(), 2);
+        assert_eq!(state.snakes()[0].length(), 1);
+
+        let events = state.step(&[]);
+        assert!(events.contains(&GameEvent::SnakeGrew {
+            snake: 0,
+            length: 2
+        }));
+        assert_eq!(
+            state.snakes()[0].body().collect::<Vec<_>>(),
+            [pos(3, 2), pos(2, 2)]
+        );
+    }
+
+    #[test]
+    fn filling_the_board_wins() {
+        let mut state = game(
+            Board::new(3, 1),
+            GameMode::Wrap,
+            vec![snake(&[(1, 0), (2, 0)], Direction::Left)],
+            Some(pos(0, 0)),
+        );
+
+        // The only free cell is where the tail just left
+        let events = state.step(&[]);
+        assert_eq!(events[1], GameEvent::FruitSpawned { pos: pos(2, 0) });
+
+        let events = state.step(&[]);
+        assert_eq!(
+            events[events.len() - 2..],
+            [GameEvent::Won, GameEvent::GameOver]
+        );
+        assert!(state.is_won());
+        assert!(state.is_over());
+        assert_eq!(state.fruit(), None);
+        assert!(state.free_cells().is_empty());
+    }
+
+    #[test]
+    fn neighbours_wrap_only_on_wrapping_axes() {
+        let rules = |mode| Rules {
+            board: Board::new(4, 3),
+            mode,
+            players: 1,
+        };
+        // Right and up from the top right corner, left and down from the
+        // bottom left one
+        let cases = [
+            (GameMode::Classic, [(4, 2), (3, 3), (-1, 0), (0, -1)]),
+            (GameMode::Wrap, [(0, 2), (3, 0), (3, 0), (0, 2)]),
+            (GameMode::WrapHorizontal, [(0, 2), (3, 3), (3, 0), (0, -1)]),
+            (GameMode::WrapVertical, [(4, 2), (3, 0), (-1, 0), (0, 2)]),
+        ];
+        for (mode, expected) in cases {
+            let rules = rules(mode);
+            let neighbours = [
+                rules.neighbour(pos(3, 2), Direction::Right),
+                rules.neighbour(pos(3, 2), Direction::Up),
+                rules.neighbour(pos(0, 0), Direction::Left),
+                rules.neighbour(pos(0, 0), Direction::Down),
+            ];
+            assert_eq!(neighbours, expected.map(|(x, y)| pos(x, y)), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn snakes_cross_wrapping_edges_and_die_at_walls() {
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::WrapHorizontal,
+            vec![
+                snake(&[(4, 1), (3, 1)], Direction::Right),
+                snake(&[(2, 4), (2, 3)], Direction::Up),
+            ],
+            Some(pos(2, 2)),
+        );
+        let events = state.step(&[]);
+        assert_eq!(events, [died(1, DeathCause::Wall)]);
+        assert_eq!(
+            state.snakes()[0].body().collect::<Vec<_>>(),
+            [pos(0, 1), pos(4, 1)]
+        );
+
+        let mut state = game(
+            Board::new(5, 5),
+            GameMode::WrapVertical,
+            vec![
+                snake(&[(2, 4), (2, 3)], Direction::Up),
+                snake(&[(4, 1), (3, 1)], Direction::Right),
+            ],
+            Some(pos(2, 2)),
+        );
+        let events = state.step(&[]);
+        assert_eq!(events, [died(1, DeathCause::Wall)]);
+        assert_eq!(
+            state.snakes()[0].body().collect::<Vec<_>>(),
+            [pos(2, 0), pos(2, 4)]
+        );
+        assert_consistent(state.free_cells());
+    }
+
+    #[test]
+    fn same_seed_and_inputs_give_the_same_game() {
+        let rules = Rules {
+            board: Board::new(12, 9),
+            mode: GameMode::Wrap,
+            players: 1,
+        };
+        // Head straight for the fruit, so the game depends on the seed
+        let play = |seed| {
+            let mut state = GameState::new(rules.clone(), seed);
+            let mut events = Vec::new();
+            while !state.is_over() && state.tick() < 500 {
+                let inputs = state
+                    .snakes()
+                    .iter()
+                    .map(|snake| {
+                        let to = state.fruit()? - snake.head();
+                        Some(match (to.x.signum(), to.y.signum()) {
+                            (1, _) => Direction::Right,
+                            (-1, _) => Direction::Left,
+                            (_, 1) => Direction::Up,
+                            _ => Direction::Down,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                events.push(state.step(&inputs));
+            }
+            events
+        };
+
+        let eaten = play(7)
+            .concat()
+            .into_iter()
+            .filter(|event| matches!(event, GameEvent::FruitEaten { .. }))
+            .count();
+        assert!(eaten > 0);
+        assert_eq!(play(7), play(7));
+        assert_ne!(play(7), play(8));
+    }
+
+    #[test]
+    fn free_cells_track_occupy_and_release() {
+        let board = Board::new(4, 3);
+        let mut free = FreeCells::new(board);
+        assert_eq!(free.len(), 12);
+        assert_consistent(&free);
+
+        free.occupy(pos(1, 1));
+        assert!(!free.is_free(pos(1, 1)));
+        assert_eq!(free.len(), 11);
+        assert_consistent(&free);
+
+        // Taking a cell twice or off the board changes nothing
+        free.occupy(pos(1, 1));
+        free.occupy(pos(4, 0));
+        free.occupy(pos(-1, 2));
+        assert_eq!(free.len(), 11);
+        assert!(!free.is_free(pos(4, 0)));
+        assert_consistent(&free);
+
+        free.release(pos(1, 1));
+        free.release(pos(1, 1));
+        free.release(pos(0, 0));
+        assert!(free.is_free(pos(1, 1)));
+        assert_eq!(free.len(), 12);
+        assert_consistent(&free);
+    }
+
+    #[test]
+    fn free_cells_occupy_the_last_slot() {
+        let mut free = FreeCells::new(Board::new(3, 3));
+        let last = *free.cells.last().unwrap();
+        free.occupy(last);
+        assert!(!free.is_free(last));
+        assert!(!free.cells.contains(&last));
+        assert_consistent(&free);
+
+        // Emptying the list from the back and the front alike
+        while let Some(&cell) = free.cells.last() {
+            free.occupy(cell);
+            assert_consistent(&free);
+            if let Some(&cell) = free.cells.first() {
+                free.occupy(cell);
+                assert_consistent(&free);
+            }
+        }
+        assert!(free.is_empty());
+    }
+
+    #[test]
+    fn free_cells_pick_only_free_cells() {
+        let board = Board::new(3, 3);
+        let mut free = FreeCells::new(board);
+        let mut rng = GameRng::seed_from_u64(3);
+        for x in 0..3 {
+            for y in 0..3 {
+                if (x, y) != (2, 1) {
+                    free.occupy(pos(x, y));
+                }
+            }
+        }
+        for _ in 0..20 {
+            assert_eq!(free.random(&mut rng), Some(pos(2, 1)));
+        }
+
+        free.occupy(pos(2, 1));
+        assert_eq!(free.random(&mut rng), None);
+    }
+}
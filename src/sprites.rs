@@ -0,0 +1,217 @@
+//! Drawing the snakes from a sprite sheet.
+//!
+//! The sheet is `assets/textures/snake.png`, a single row of four square
+//! tiles of any size, all drawn for a snake moving up:
+//!
+//! | Index | Tile     | Connects                                  |
+//! |-------|----------|-------------------------------------------|
+//! | 0     | Head     | the neck at the bottom edge               |
+//! | 1     | Straight | the bottom and the top edge               |
+//! | 2     | Corner   | the bottom and the right edge             |
+//! | 3     | Tail     | the rest of the body at the top edge      |
+//!
+//! Every part is rotated to fit its neighbours and tinted with the color of
+//! its snake, so the tiles should be white where the color should show. As
+//! long as the sheet is missing the parts are drawn as flat squares.
+
+use bevy::{prelude::*, reflect::TypeUuid, render::texture::DEFAULT_IMAGE_HANDLE, sprite::Rect};
+
+use crate::{
+    board::SNAKE_Z,
+    game::{SnakeHead, SnakePart},
+    interpolation::{tween_target_system, Tween},
+    sim::{Direction, GameState},
+};
+
+/// The atlas every snake part is drawn from. Starts out as flat squares and is
+/// replaced by the sprite sheet once that is loaded
+pub const SNAKE_ATLAS: HandleUntyped =
+    HandleUntyped::weak_from_u64(TextureAtlas::TYPE_UUID, 0x5a4b_e5b1_7e5c_0a11);
+
+/// Path of the sprite sheet below the assets folder
+const SNAKE_SHEET: &str = "textures/snake.png";
+/// Directions a part can face
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// Plugin picking the tile and rotation of every snake part
+pub struct SnakeSpritesPlugin;
+
+impl Plugin for SnakeSpritesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(load_snake_sheet_system)
+            .add_system(snake_sheet_loaded_system)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                snake_tile_system.after(tween_target_system),
+            );
+    }
+}
+
+/// The tiles of the sprite sheet, by their index in the atlas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnakeTile {
+    Head = 0,
+    Straight = 1,
+    Corner = 2,
+    Tail = 3,
+}
+
+impl SnakeTile {
+    /// Offset from [`SNAKE_Z`]. The head slides over the body and the tail
+    /// slides under it
+    pub fn layer(self) -> f32 {
+        match self {
+            SnakeTile::Head => 0.2,
+            SnakeTile::Straight | SnakeTile::Corner => 0.1,
+            SnakeTile::Tail => 0.0,
+        }
+    }
+}
+
+/// The sprite sheet while it is still loading
+#[derive(Debug, Clone, Default)]
+pub struct SnakeSheet(Option<Handle<Image>>);
+
+/// Put the flat squares in place and start loading the sprite sheet
+pub fn load_snake_sheet_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    // Every tile is the whole white default image
+    let mut flat = TextureAtlas::new_empty(DEFAULT_IMAGE_HANDLE.typed(), Vec2::ONE);
+    for _ in 0..4 {
+        flat.add_texture(Rect {
+            min: Vec2::ZERO,
+            max: Vec2::ONE,
+        });
+    }
+    atlases.set_untracked(SNAKE_ATLAS.id, flat);
+
+    commands.insert_resource(SnakeSheet(Some(asset_server.load(SNAKE_SHEET))));
+}
+
+/// Switch to the sprite sheet once it is loaded
+pub fn snake_sheet_loaded_system(
+    mut sheet: ResMut<SnakeSheet>,
+    asset_server: Res<AssetServer>,
+    images: Res<Assets<Image>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let handle = match &sheet.0 {
+        Some(handle) => handle.clone(),
+        None => return,
+    };
+
+    match asset_server.get_load_state(&handle) {
+        bevy::asset::LoadState::Loaded => {
+            if let Some(image) = images.get(&handle) {
+                let size = image.size();
+                let tile = Vec2::new(size.x / 4.0, size.y);
+                atlases.set_untracked(SNAKE_ATLAS.id, TextureAtlas::from_grid(handle, tile, 4, 1));
+                sheet.0 = None;
+            }
+        }
+        bevy::asset::LoadState::Failed => {
+            warn!("No snake sprites in {}, drawing flat colors", SNAKE_SHEET);
+            sheet.0 = None;
+        }
+        _ => {}
+    }
+}
+
+/// Pick the tile and rotation of every snake part from its neighbours
+pub fn snake_tile_system(
+    state: Res<GameState>,
+    heads: Query<(Entity, &SnakeHead)>,
+    added: Query<(), Added<SnakePart>>,
+    mut parts: Query<(&mut TextureAtlasSprite, &mut Transform, &Tween), With<SnakePart>>,
+) {
+    if !state.is_changed() && added.is_empty() {
+        return;
+    }
+
+    for (head, snake_head) in heads.iter() {
+        let snake = match state.snakes().get(snake_head.snake()) {
+            Some(snake) => snake,
+            None => continue,
+        };
+        let cells = snake.body().collect::<Vec<_>>();
+        let entities = std::iter::once(head).chain(snake_head.tail().iter().copied());
+
+        for (idx, entity) in entities.enumerate().take(cells.len()) {
+            let (tile, facing) = snake_tile(&state, &cells, idx, snake.direction());
+            if let Ok((mut sprite, mut transform, tween)) = parts.get_mut(entity) {
+                // The tail points where it slides to, also around a corner
+                let facing = match tile {
+                    SnakeTile::Tail => tween.direction().unwrap_or(facing),
+                    _ => facing,
+                };
+                sprite.index = tile as usize;
+                transform.rotation = rotation(facing);
+                transform.translation.z = SNAKE_Z + tile.layer();
+            }
+        }
+    }
+}
+
+/// The tile of the part at `idx` of a snake made of `cells`, head first, and
+/// the direction its tile has to face
+pub fn snake_tile(
+    state: &GameState,
+    cells: &[IVec2],
+    idx: usize,
+    heading: Option<Direction>,
+) -> (SnakeTile, Direction) {
+    let towards = |to: usize| {
+        cells
+            .get(to)
+            .and_then(|&to| direction_between(state, cells[idx], to))
+    };
+    let previous = idx.checked_sub(1).and_then(towards);
+    let next = towards(idx + 1);
+
+    match (previous, next) {
+        // A snake that has not moved yet faces up
+        (None, None) => (SnakeTile::Head, heading.unwrap_or(Direction::Up)),
+        (None, Some(neck)) => (SnakeTile::Head, neck.opposite()),
+        (Some(front), None) => (SnakeTile::Tail, front),
+        (Some(front), Some(back)) if front == back.opposite() => (SnakeTile::Straight, front),
+        (Some(front), Some(back)) => {
+            // The corner connects the bottom and the right edge, find the
+            // rotation that turns those towards both neighbours
+            let facing = DIRECTIONS
+                .into_iter()
+                .find(|facing| {
+                    let edges = [facing.opposite(), facing.turned_right()];
+                    edges.contains(&front) && edges.contains(&back)
+                })
+                .unwrap_or(front);
+            (SnakeTile::Corner, facing)
+        }
+    }
+}
+
+/// The direction from one cell to a neighbouring cell, also across the edge
+/// of the board if the mode wraps around
+fn direction_between(state: &GameState, from: IVec2, to: IVec2) -> Option<Direction> {
+    DIRECTIONS
+        .into_iter()
+        .find(|&direction| state.rules().neighbour(from, direction) == to)
+}
+
+/// The rotation turning a tile drawn facing up to face `direction`
+fn rotation(direction: Direction) -> Quat {
+    let angle = match direction {
+        Direction::Up => 0.0,
+        Direction::Left => std::f32::consts::FRAC_PI_2,
+        Direction::Down => std::f32::consts::PI,
+        Direction::Right => -std::f32::consts::FRAC_PI_2,
+    };
+    Quat::from_rotation_z(angle)
+}
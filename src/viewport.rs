@@ -0,0 +1,71 @@
+//! Fitting the board into the window.
+//!
+//! The camera is zoomed so the board and its walls fill as much of the window
+//! as they can without being stretched or cut off. What is left over on the
+//! sides shows the background color. F11 switches between a window and
+//! fullscreen.
+
+use bevy::{
+    prelude::*,
+    window::{WindowMode, WindowResized},
+};
+
+use crate::{board::field_size, config::GameConfig};
+
+/// Space kept free around the walls, in cells
+const MARGIN: f32 = 0.5;
+
+/// Plugin keeping the board in view whatever the size of the window
+pub struct ViewportPlugin;
+
+impl Plugin for ViewportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(fit_board_system)
+            .add_system(toggle_fullscreen_system);
+    }
+}
+
+/// Zoom the camera to fit the board whenever the window or the board changed
+/// size
+pub fn fit_board_system(
+    mut resized: EventReader<WindowResized>,
+    windows: Res<Windows>,
+    config: Res<GameConfig>,
+    mut projections: Query<&mut OrthographicProjection, With<Camera2d>>,
+) {
+    if resized.iter().last().is_none() && !config.is_changed() {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width(), window.height()),
+        None => return,
+    };
+
+    let scale = fit_scale(&config, window);
+    for mut projection in projections.iter_mut() {
+        projection.scale = scale;
+    }
+}
+
+/// World units per logical pixel that fit the board into a window of the
+/// given size
+pub fn fit_scale(config: &GameConfig, window: Vec2) -> f32 {
+    let field = field_size(config) + Vec2::splat(MARGIN * config.cell_size * 2.0);
+    // A minimized window has no size to fit into
+    let window = window.max(Vec2::ONE);
+    (field / window).max_element()
+}
+
+/// Switch between a window and fullscreen
+pub fn toggle_fullscreen_system(mut input: ResMut<Input<KeyCode>>, mut windows: ResMut<Windows>) {
+    if !input.clear_just_pressed(KeyCode::F11) {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = match window.mode() {
+            WindowMode::Windowed => WindowMode::BorderlessFullscreen,
+            _ => WindowMode::Windowed,
+        };
+        window.set_mode(mode);
+    }
+}